@@ -0,0 +1,11 @@
+#![no_main]
+
+use std::io::Cursor;
+
+use archive_dl::resource_parse::ResrcDependency;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Some((offset, data)) = data.split_first_chunk::<4>() else { return };
+    let _ = ResrcDependency::parse_table(&mut Cursor::new(data), u32::from_be_bytes(*offset));
+});
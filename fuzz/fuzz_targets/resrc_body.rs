@@ -0,0 +1,8 @@
+#![no_main]
+
+use archive_dl::resource_parse::ResrcBody;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = ResrcBody::new(data);
+});
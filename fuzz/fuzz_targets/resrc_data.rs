@@ -0,0 +1,9 @@
+#![no_main]
+
+use archive_dl::resource_parse::ResrcData;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = ResrcData::new(data, false);
+    let _ = ResrcData::new(data, true);
+});
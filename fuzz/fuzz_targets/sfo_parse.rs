@@ -0,0 +1,10 @@
+#![no_main]
+
+use archive_dl::serializers::ps3::Sfo;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(sfo) = Sfo::parse(data) {
+        let _ = sfo.serialize();
+    }
+});
@@ -0,0 +1,10 @@
+#![no_main]
+
+use std::io::Cursor;
+
+use archive_dl::resource_parse::decompress_chunks;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = decompress_chunks(&mut Cursor::new(data));
+});
@@ -1,10 +1,32 @@
 use std::{fs::{self, File}, io::Write, path::{Path, PathBuf}};
-use anyhow::{Context, Result};
 use serde::Deserialize;
+use thiserror::Error;
+
+use crate::db::Region;
 
 const DEFAULT_CONFIG: &[u8] = include_bytes!("assets/default_config.yml");
 
-#[derive(Debug, Deserialize)]
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("Couldn't open config file: {0}")]
+    Open(std::io::Error),
+    #[error("Couldn't backup old config: {0}")]
+    Backup(std::io::Error),
+    #[error("{0} cannot be set to zero")]
+    Zero(&'static str),
+    #[error("{0} is not a valid title ID, it should look like BCES01663")]
+    InvalidTitleId(String),
+    #[error("No known {0} title ID for region {1}, set title_id instead")]
+    UnknownTitleId(&'static str, &'static str),
+    #[error("yaml error: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+    #[error("io error: {0}")]
+    IOError(#[from] std::io::Error),
+}
+
+type Result<T> = std::result::Result<T, ConfigError>;
+
+#[derive(Debug, Clone, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum DownloadServer {
     Bonsai,
@@ -14,6 +36,15 @@ pub enum DownloadServer {
 }
 
 impl DownloadServer {
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            Self::Bonsai => "Bonsai",
+            Self::Refresh => "Refresh",
+            Self::LbpSearch => "LBP Search",
+            Self::Archive => "Internet Archive",
+        }
+    }
+
     pub fn get_url(&self, sha1: &[u8; 20]) -> String {
         let h = hex::encode(sha1);
         match self {
@@ -33,6 +64,13 @@ pub struct Config {
     pub fix_backup_version: bool,
     pub force_lbp3_backups: bool,
     pub lbp2_beta_to_retail: bool,
+    pub upgrade_mixed_revisions: bool,
+    pub generate_pic1: bool,
+    pub region: Region,
+    pub title_id: Option<String>,
+    pub detail_template: String,
+    pub guid_map_path: Option<PathBuf>,
+    pub game_data_directory: Option<PathBuf>,
 }
 
 impl Config {
@@ -47,7 +85,7 @@ impl Config {
             return Ok(config)
         }
 
-        let file = File::open(config_path).context("Couldn't open config file")?;
+        let file = File::open(config_path).map_err(ConfigError::Open)?;
         let config: std::result::Result<Self, serde_yaml::Error> = serde_yaml::from_reader(file);
         
         match config {
@@ -55,7 +93,7 @@ impl Config {
             Err(_) => {
                 eprintln!("WARNING: config.yml is from an old version or broken, writing default config");
 
-                fs::copy(config_path, "config_backup.yml").context("Couldn't backup old config")?;
+                fs::copy(config_path, "config_backup.yml").map_err(ConfigError::Backup)?;
                 eprintln!("WARNING: Old config written to config_backup.yml");
 
                 let mut new_file = File::create(config_path)?;
@@ -1,10 +1,27 @@
-use std::path::Path;
+use std::{path::Path, str::FromStr};
 
 use bitvec::{order::Lsb0, view::BitView};
+use serde::Deserialize;
 use sqlite::State;
-use anyhow::{anyhow, Result};
+use thiserror::Error;
 
-use crate::{labels::LABEL_LAMS_KEY_IDS, resource_parse::ResrcRevision, ResrcDescriptor};
+use crate::{labels::LABEL_LAMS_KEY_IDS, resource_parse::{ResrcDescriptor, ResrcRevision}};
+
+#[derive(Error, Debug)]
+pub enum DbError {
+    #[error("Database file is missing, download it or check if the path in config.yml is correct")]
+    Missing,
+    #[error("Level not found")]
+    LevelNotFound,
+    #[error("Multiple levels found with the same id")]
+    DuplicateLevel,
+    #[error("invalid {0} in db")]
+    InvalidField(&'static str),
+    #[error("sqlite error: {0}")]
+    Sqlite(#[from] sqlite::Error),
+}
+
+type Result<T> = std::result::Result<T, DbError>;
 
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum GameVersion {
@@ -28,41 +45,189 @@ impl GameVersion {
             Self::Lbp3 => "LBP3",
         }
     }
-    pub fn get_titleid(&self) -> &'static str {
-        match self {
-            Self::Lbp1 => "BCES00141",
-            Self::Lbp2 => "BCES00850",
-            Self::Lbp3 => "BCES01663",
+    /// Title ID of this game's release in a region, if it's known
+    pub fn get_titleid(&self, region: Region) -> Option<&'static str> {
+        match (self, region) {
+            (Self::Lbp1, Region::Bces) => Some("BCES00141"),
+            (Self::Lbp1, Region::Bcus) => Some("BCUS98148"),
+            (Self::Lbp1, Region::Bcjs) => Some("BCJS30018"),
+            (Self::Lbp1, Region::Bcas) => Some("BCAS20058"),
+            (Self::Lbp1, Region::Npua) => Some("NPUA80472"),
+            (Self::Lbp1, Region::Npea) => Some("NPEA00241"),
+            (Self::Lbp2, Region::Bces) => Some("BCES00850"),
+            (Self::Lbp2, Region::Bcus) => Some("BCUS98245"),
+            (Self::Lbp2, Region::Bcjs) => Some("BCJS30058"),
+            (Self::Lbp2, Region::Bcas) => Some("BCAS20113"),
+            (Self::Lbp2, Region::Npua) => Some("NPUA80662"),
+            (Self::Lbp2, Region::Npea) => Some("NPEA00324"),
+            (Self::Lbp3, Region::Bces) => Some("BCES01663"),
+            (Self::Lbp3, Region::Bcus) => Some("BCUS98362"),
+            (Self::Lbp3, Region::Bcjs) => Some("BCJS30095"),
+            (Self::Lbp3, Region::Bcas) => Some("BCAS20322"),
+            (Self::Lbp3, Region::Npua) => Some("NPUA81116"),
+            (Self::Lbp3, Region::Npea) => Some("NPEA00515"),
         }
     }
     pub fn get_latest_revision(&self) -> ResrcRevision {
         match self {
-            Self::Lbp1 => ResrcRevision {
-                head: 0x272,
-                branch_id: 0x4c44,
-                branch_revision: 0x17,
-            },
-            Self::Lbp2 => ResrcRevision {
-                head: 0x3f8,
-                branch_id: 0x0,
-                branch_revision: 0x0,
-            },
-            Self::Lbp3 => ResrcRevision {
-                head: 0x21803f9,
-                branch_id: 0x0,
-                branch_revision: 0x0,
-            },
+            Self::Lbp1 => LBP1_LATEST,
+            Self::Lbp2 => LBP2_LATEST,
+            Self::Lbp3 => LBP3_LATEST,
         }
     }
+    /// Game a known title ID belongs to, also matches save directory names starting with one
+    pub fn from_titleid(titleid: &str) -> Option<Self> {
+        let regions = [Region::Bces, Region::Bcus, Region::Bcjs, Region::Bcas, Region::Npua, Region::Npea];
+        [Self::Lbp1, Self::Lbp2, Self::Lbp3].into_iter().find(|game| {
+            regions.iter().any(|region| game.get_titleid(*region).is_some_and(|id| titleid.starts_with(id)))
+        })
+    }
 }
 
-#[derive(Debug)]
+/// Release the backup is made for, named after the title ID prefix
+#[derive(Debug, PartialEq, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Region {
+    /// Europe disc
+    Bces,
+    /// North America disc
+    Bcus,
+    /// Japan disc
+    Bcjs,
+    /// Asia disc
+    Bcas,
+    /// North America digital
+    Npua,
+    /// Europe digital
+    Npea,
+}
+
+impl Region {
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            Self::Bces => "BCES",
+            Self::Bcus => "BCUS",
+            Self::Bcjs => "BCJS",
+            Self::Bcas => "BCAS",
+            Self::Npua => "NPUA",
+            Self::Npea => "NPEA",
+        }
+    }
+}
+
+impl FromStr for Region {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "bces" => Ok(Self::Bces),
+            "bcus" => Ok(Self::Bcus),
+            "bcjs" => Ok(Self::Bcjs),
+            "bcas" => Ok(Self::Bcas),
+            "npua" => Ok(Self::Npua),
+            "npea" => Ok(Self::Npea),
+            _ => Err(format!("unknown region {s}, expected one of bces, bcus, bcjs, bcas, npua, npea")),
+        }
+    }
+}
+
+/// Checks for the usual title ID format, four letters followed by five digits (BCES01663)
+pub fn is_valid_titleid(titleid: &str) -> bool {
+    let bytes = titleid.as_bytes();
+    bytes.len() == 9
+        && bytes[..4].iter().all(u8::is_ascii_uppercase)
+        && bytes[4..].iter().all(u8::is_ascii_digit)
+}
+
+const LBP1_LATEST: ResrcRevision = ResrcRevision {
+    head: 0x272,
+    branch_id: 0x4c44,
+    branch_revision: 0x17,
+};
+const LBP2_LATEST: ResrcRevision = ResrcRevision {
+    head: 0x3f8,
+    branch_id: 0x0,
+    branch_revision: 0x0,
+};
+const LBP3_LATEST: ResrcRevision = ResrcRevision {
+    head: 0x21803f9,
+    branch_id: 0x0,
+    branch_revision: 0x0,
+};
+
+pub struct KnownRevision {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub revision: ResrcRevision,
+}
+
+/// Revisions that can be picked by name as a backup target
+pub const KNOWN_REVISIONS: [KnownRevision; 5] = [
+    KnownRevision {
+        name: "lbp1-unbranched",
+        description: "LBP1, last patch before the Leerdammer (0x4c44) branch",
+        revision: ResrcRevision {
+            head: 0x272,
+            branch_id: 0x0,
+            branch_revision: 0x0,
+        },
+    },
+    KnownRevision {
+        name: "lbp1",
+        description: "LBP1, latest patch",
+        revision: LBP1_LATEST,
+    },
+    KnownRevision {
+        name: "lbp2-production",
+        description: "LBP2, first revision marked as a production build",
+        revision: ResrcRevision {
+            head: 0x3b6,
+            branch_id: 0x0,
+            branch_revision: 0x0,
+        },
+    },
+    KnownRevision {
+        name: "lbp2",
+        description: "LBP2, latest patch",
+        revision: LBP2_LATEST,
+    },
+    KnownRevision {
+        name: "lbp3",
+        description: "LBP3, latest patch",
+        revision: LBP3_LATEST,
+    },
+];
+
+#[derive(Debug, Clone, Copy)]
 pub enum LevelType {
     Cooperative,
     Versus,
     Cutscene,
 }
 
+impl LevelType {
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            Self::Cooperative => "Cooperative",
+            Self::Versus => "Versus",
+            Self::Cutscene => "Cutscene",
+        }
+    }
+}
+
+impl FromStr for LevelType {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "cooperative" => Ok(Self::Cooperative),
+            "versus" => Ok(Self::Versus),
+            "cutscene" => Ok(Self::Cutscene),
+            _ => Err(format!("unknown level type {s}, expected one of cooperative, versus, cutscene")),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct SlotInfo {
     pub name: String,
@@ -82,9 +247,18 @@ pub struct SlotInfo {
     pub is_adventure_planet: bool,
 }
 
+impl SlotInfo {
+    pub fn get_display_name(&self) -> &str {
+        match self.name.is_empty() {
+            false => &self.name,
+            true => "Unnamed Level",
+        }
+    }
+}
+
 pub fn get_slot_info(id: i64, db_path: &Path) -> Result<SlotInfo> {
     if !db_path.exists() {
-        return Err(anyhow!("Database file is missing, download it or check if the path in config.yml is correct"));
+        return Err(DbError::Missing);
     }
 
     let db = sqlite::open(db_path)?;
@@ -96,7 +270,7 @@ pub fn get_slot_info(id: i64, db_path: &Path) -> Result<SlotInfo> {
     statement.bind((1, id))?;
 
     match statement.next()? {
-        State::Done => return Err(anyhow!("Level not found")),
+        State::Done => return Err(DbError::LevelNotFound),
         State::Row => {},
     }
 
@@ -104,19 +278,19 @@ pub fn get_slot_info(id: i64, db_path: &Path) -> Result<SlotInfo> {
         name: statement.read::<Option<String>, _>("name")?.unwrap_or_default(),
         description: statement.read::<Option<String>, _>("description")?.unwrap_or_default(),
         np_handle: statement.read::<String, _>("npHandle")?,
-        root_level: statement.read::<Vec<u8>, _>("rootLevel")?.try_into().map_err(|_| anyhow!("invalid rootLevel in db"))?,
+        root_level: statement.read::<Vec<u8>, _>("rootLevel")?.try_into().map_err(|_| DbError::InvalidField("rootLevel"))?,
         icon: {
             let bytes = statement.read::<Vec<u8>, _>("icon")?;
             match bytes.len() {
-                20 => ResrcDescriptor::Sha1(bytes.try_into().map_err(|_| anyhow!("invalid icon in db"))?),
+                20 => ResrcDescriptor::Sha1(bytes.try_into().map_err(|_| DbError::InvalidField("icon"))?),
                 4 => {
-                    let bytes = bytes.try_into().map_err(|_| anyhow!("invalid icon in db"))?;
+                    let bytes = bytes.try_into().map_err(|_| DbError::InvalidField("icon"))?;
                     ResrcDescriptor::Guid(u32::from_be_bytes(bytes))
                 },
                 0 => {
                     ResrcDescriptor::Guid(0)
                 },
-                _ => return Err(anyhow!("invalid icon in db")),
+                _ => return Err(DbError::InvalidField("icon")),
             }
         },
         game: {
@@ -125,7 +299,7 @@ pub fn get_slot_info(id: i64, db_path: &Path) -> Result<SlotInfo> {
                 0 => GameVersion::Lbp1,
                 1 => GameVersion::Lbp2,
                 2 => GameVersion::Lbp3,
-                _ => return Err(anyhow!("invalid game version in db")),
+                _ => return Err(DbError::InvalidField("game version")),
             }
         },
         initially_locked: statement.read::<i64, _>("initiallyLocked")? == 1,
@@ -139,7 +313,7 @@ pub fn get_slot_info(id: i64, db_path: &Path) -> Result<SlotInfo> {
             if let Some(arr) = &bytes {
                 let bits = arr.view_bits::<Lsb0>();
                 for (i, key_id) in LABEL_LAMS_KEY_IDS.iter().enumerate() {
-                    if bits[i] {
+                    if bits.get(i).is_some_and(|bit| *bit) {
                         labels.push(*key_id);
                     }
                 }
@@ -153,14 +327,35 @@ pub fn get_slot_info(id: i64, db_path: &Path) -> Result<SlotInfo> {
                 None => LevelType::Cooperative,
                 Some("versus") => LevelType::Versus,
                 Some("cutscene") => LevelType::Cutscene,
-                _ => return Err(anyhow!("invalid leveltype in db")),
+                _ => return Err(DbError::InvalidField("leveltype")),
             }
         },
         min_players: statement.read::<Option<i64>, _>("minPlayers")?.map(|i| i as u8),
         max_players: statement.read::<Option<i64>, _>("maxPlayers")?.map(|i| i as u8),
         is_adventure_planet: statement.read::<i64, _>("isAdventurePlanet")? == 1,
     };
-    assert!(matches!(statement.next(), Ok(State::Done)));
+    if let State::Row = statement.next()? {
+        return Err(DbError::DuplicateLevel);
+    }
 
     Ok(slot_info)
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ps3_titleids_in_every_region() {
+        let regions = [Region::Bces, Region::Bcus, Region::Bcjs, Region::Bcas, Region::Npua, Region::Npea];
+        for game in [GameVersion::Lbp1, GameVersion::Lbp2, GameVersion::Lbp3] {
+            for region in regions {
+                let titleid = game.get_titleid(region).unwrap();
+                assert!(is_valid_titleid(titleid));
+                assert!(titleid.starts_with(region.get_name()));
+                assert_eq!(GameVersion::from_titleid(&format!("{titleid}LEVEL0000002A")), Some(game));
+            }
+        }
+        assert_eq!(GameVersion::from_titleid("BLUS12345"), None);
+    }
+}
@@ -0,0 +1,135 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{db::{GameVersion, SlotInfo}, labels::get_label_name};
+
+/// Where a backup came from, for the DETAIL text in the save menu
+pub struct Provenance<'a> {
+    pub level_id: Option<i64>,
+    pub source: &'a str,
+    pub missing: usize,
+}
+
+// civil date from days since the unix epoch, from Howard Hinnant's date algorithms
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    (year, month, day)
+}
+
+fn today() -> String {
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let (year, month, day) = civil_from_days((secs / 86400) as i64);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+fn get_players(slot_info: &SlotInfo) -> String {
+    let min = slot_info.min_players.unwrap_or(1);
+    let max = slot_info.max_players.unwrap_or(4);
+    match min == max {
+        true => min.to_string(),
+        false => format!("{min}-{max}"),
+    }
+}
+
+fn get_labels(slot_info: &SlotInfo) -> String {
+    let names: Vec<_> = slot_info.author_labels.iter()
+        .filter_map(|key_id| get_label_name(*key_id))
+        .map(|name| name.replace('_', " "))
+        .collect();
+    names.join(", ")
+}
+
+// Replaces every placeholder in one pass, so values containing placeholders are kept as they are.
+// Returns None if the line has placeholders and all of them are empty
+fn fill_line(line: &str, values: &[(&str, String)]) -> Option<String> {
+    let mut filled = String::new();
+    let mut any_used = false;
+    let mut any_value = false;
+
+    let mut rest = line;
+    while let Some(start) = rest.find('{') {
+        filled.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let placeholder = rest.find('}')
+            .map(|end| &rest[..=end])
+            .and_then(|placeholder| values.iter().find(|(key, _)| *key == placeholder));
+        match placeholder {
+            Some((key, value)) => {
+                filled.push_str(value);
+                any_used = true;
+                any_value |= !value.is_empty();
+                rest = &rest[key.len()..];
+            },
+            None => {
+                filled.push('{');
+                rest = &rest[1..];
+            },
+        }
+    }
+    filled.push_str(rest);
+
+    (!any_used || any_value).then_some(filled)
+}
+
+/// Fills in a DETAIL template for the game the backup is written for. Lines where every placeholder
+/// came out empty are left out, the result is truncated to fit by `make_sfo`
+pub fn make_detail(template: &str, slot_info: &SlotInfo, gamever: &GameVersion, provenance: &Provenance) -> String {
+    let values = [
+        ("{name}", slot_info.get_display_name().to_string()),
+        ("{description}", slot_info.description.clone()),
+        ("{level_id}", provenance.level_id.map(|id| id.to_string()).unwrap_or_default()),
+        ("{creator}", slot_info.np_handle.clone()),
+        ("{game}", gamever.get_short_title().to_string()),
+        ("{level_type}", slot_info.leveltype.get_name().to_string()),
+        ("{players}", get_players(slot_info)),
+        ("{labels}", get_labels(slot_info)),
+        ("{source}", provenance.source.to_string()),
+        ("{date}", today()),
+        ("{missing}", provenance.missing.to_string()),
+    ];
+
+    let lines: Vec<_> = template.lines()
+        .filter_map(|line| fill_line(line, &values))
+        .collect();
+
+    lines.join("\n").trim().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values() -> Vec<(&'static str, String)> {
+        vec![
+            ("{description}", "made by {creator} in {game}".to_string()),
+            ("{creator}", "Sackboy".to_string()),
+            ("{labels}", String::new()),
+        ]
+    }
+
+    #[test]
+    fn single_pass() {
+        let values = values();
+        assert_eq!(
+            fill_line("{description} - {creator}", &values).as_deref(),
+            Some("made by {creator} in {game} - Sackboy"),
+        );
+        // unknown and unclosed braces are left alone
+        assert_eq!(fill_line("{x} {creator} {", &values).as_deref(), Some("{x} Sackboy {"));
+    }
+
+    #[test]
+    fn empty_lines_dropped() {
+        let values = values();
+        assert_eq!(fill_line("Labels: {labels}", &values), None);
+        assert_eq!(fill_line("{labels} by {creator}", &values).as_deref(), Some(" by Sackboy"));
+        assert_eq!(fill_line("no placeholders", &values).as_deref(), Some("no placeholders"));
+    }
+}
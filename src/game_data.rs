@@ -0,0 +1,258 @@
+use std::{collections::HashMap, fs::{self, File}, io::{Read, Seek, SeekFrom}, path::{Path, PathBuf}};
+
+use byteorder::{BigEndian, ReadBytesExt};
+use thiserror::Error;
+
+use crate::guid_map::{GuidMap, GuidMapError};
+
+#[derive(Error, Debug)]
+pub enum GameDataError {
+    #[error("Couldn't read game data {0}: {1}")]
+    Open(String, std::io::Error),
+    #[error("{0} isn't a valid FARC archive")]
+    InvalidFarc(String),
+    #[error("guid map error: {0}")]
+    GuidMap(#[from] GuidMapError),
+    #[error("io error: {0}")]
+    IOError(#[from] std::io::Error),
+}
+
+type Result<T> = std::result::Result<T, GameDataError>;
+
+const FARC_ENTRY_SIZE: u64 = 0x1c;
+
+#[derive(Debug)]
+enum LocalResource {
+    Farc {
+        farc: usize,
+        offset: u32,
+        size: u32,
+    },
+    File {
+        path: PathBuf,
+        size: u32,
+    },
+}
+
+impl LocalResource {
+    fn get_size(&self) -> u32 {
+        match self {
+            Self::Farc { size, .. } | Self::File { size, .. } => *size,
+        }
+    }
+}
+
+/// Resources that are already present in a local copy of the game,
+/// either packed in FARC archives or as loose files listed in a GUID map.
+#[derive(Debug, Default)]
+pub struct GameData {
+    pub guid_map: GuidMap,
+    farcs: Vec<PathBuf>,
+    resources: HashMap<[u8; 20], LocalResource>,
+}
+
+impl GameData {
+    pub fn new(guid_map: GuidMap) -> Self {
+        Self {
+            guid_map,
+            ..Default::default()
+        }
+    }
+
+    pub fn index_directory(&mut self, dir: &Path) -> Result<()> {
+        let mut maps = Vec::new();
+        let mut farcs = Vec::new();
+        find_files(dir, &mut maps, &mut farcs)?;
+
+        for path in maps {
+            self.guid_map.extend(GuidMap::read(&path)?);
+        }
+
+        for path in farcs {
+            self.index_farc(path)?;
+        }
+
+        // loose files, map paths are relative to the game data root
+        for (_, entry) in self.guid_map.iter() {
+            let path = dir.join(&entry.path);
+            if !self.resources.contains_key(&entry.sha1) && path.is_file() {
+                self.resources.insert(entry.sha1, LocalResource::File { path, size: entry.size });
+            }
+        }
+
+        Ok(())
+    }
+
+    fn index_farc(&mut self, path: PathBuf) -> Result<()> {
+        let open_err = |e| GameDataError::Open(path.display().to_string(), e);
+        let invalid_err = || GameDataError::InvalidFarc(path.display().to_string());
+
+        let mut file = File::open(&path).map_err(open_err)?;
+        let file_len = file.metadata()?.len();
+        if file_len < 8 {
+            return Err(invalid_err());
+        }
+
+        // footer: entry count, then the magic
+        file.seek(SeekFrom::End(-8))?;
+        let count = file.read_u32::<BigEndian>()? as u64;
+        let mut magic = [0u8; 4];
+        file.read_exact(&mut magic)?;
+        if &magic != b"FARC" {
+            return Err(invalid_err());
+        }
+
+        let table_size = count * FARC_ENTRY_SIZE;
+        if table_size + 8 > file_len {
+            return Err(invalid_err());
+        }
+        file.seek(SeekFrom::Start(file_len - 8 - table_size))?;
+
+        let mut table = vec![0u8; table_size as usize];
+        file.read_exact(&mut table)?;
+        let mut table = table.as_slice();
+
+        let farc = self.farcs.len();
+        for _ in 0..count {
+            let mut sha1 = [0u8; 20];
+            table.read_exact(&mut sha1)?;
+            let offset = table.read_u32::<BigEndian>()?;
+            let size = table.read_u32::<BigEndian>()?;
+
+            self.resources.insert(sha1, LocalResource::Farc { farc, offset, size });
+        }
+        self.farcs.push(path);
+
+        Ok(())
+    }
+
+    /// Reads a resource from the game data, if it's present
+    pub fn read(&self, sha1: &[u8; 20]) -> Result<Option<Vec<u8>>> {
+        let data = match self.resources.get(sha1) {
+            None => return Ok(None),
+            Some(LocalResource::File { path, .. }) => fs::read(path)?,
+            Some(LocalResource::Farc { farc, offset, size }) => {
+                let mut file = File::open(&self.farcs[*farc])?;
+                file.seek(SeekFrom::Start(*offset as u64))?;
+                let mut data = vec![0u8; *size as usize];
+                file.read_exact(&mut data)?;
+                data
+            },
+        };
+        Ok(Some(data))
+    }
+
+    /// Size of a resource if it's present in the game data
+    pub fn get_size(&self, sha1: &[u8; 20]) -> Option<u32> {
+        self.resources.get(sha1).map(LocalResource::get_size)
+    }
+
+    pub fn contains(&self, sha1: &[u8; 20]) -> bool {
+        self.resources.contains_key(sha1)
+    }
+
+    pub fn len(&self) -> usize {
+        self.resources.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.resources.is_empty()
+    }
+}
+
+fn find_files(dir: &Path, maps: &mut Vec<PathBuf>, farcs: &mut Vec<PathBuf>) -> Result<()> {
+    let entries = fs::read_dir(dir).map_err(|e| GameDataError::Open(dir.display().to_string(), e))?;
+    for entry in entries {
+        let path = entry?.path();
+        if path.is_dir() {
+            find_files(&path, maps, farcs)?;
+            continue;
+        }
+
+        let extension = path.extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("map") => maps.push(path),
+            Some("farc") => farcs.push(path),
+            _ => {},
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use sha1::{Digest, Sha1};
+
+    fn make_farc(resources: &[&[u8]]) -> Vec<u8> {
+        let mut farc = Vec::new();
+        let mut table = Vec::new();
+        for data in resources {
+            table.extend(Sha1::digest(data));
+            table.extend((farc.len() as u32).to_be_bytes());
+            table.extend((data.len() as u32).to_be_bytes());
+            farc.extend(*data);
+        }
+        farc.extend(table);
+        farc.extend((resources.len() as u32).to_be_bytes());
+        farc.extend(b"FARC");
+        farc
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("archive_dl_{name}_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn farc_lookup() {
+        let sha1 = |data: &[u8]| -> [u8; 20] { Sha1::digest(data).into() };
+        let dir = temp_dir("farc_lookup");
+        fs::write(dir.join("data.farc"), make_farc(&[b"mesh", b"texture data"])).unwrap();
+
+        let mut game_data = GameData::default();
+        let indexed = game_data.index_directory(&dir);
+        let texture = game_data.read(&sha1(b"texture data"));
+        let missing = game_data.read(&sha1(b"plan"));
+        fs::remove_dir_all(&dir).unwrap();
+
+        indexed.unwrap();
+        assert_eq!(game_data.len(), 2);
+        assert!(game_data.contains(&sha1(b"mesh")));
+        assert!(!game_data.contains(&sha1(b"plan")));
+        assert_eq!(game_data.get_size(&sha1(b"mesh")), Some(4));
+        assert_eq!(texture.unwrap().as_deref(), Some(&b"texture data"[..]));
+        assert!(missing.unwrap().is_none());
+    }
+
+    #[test]
+    fn malformed_farcs() {
+        let dir = temp_dir("malformed_farcs");
+        let farc = make_farc(&[b"mesh"]);
+
+        let mut bad_magic = farc.clone();
+        let len = bad_magic.len();
+        bad_magic[len - 4..].copy_from_slice(b"CRAF");
+
+        let mut bad_count = farc.clone();
+        bad_count[len - 8..len - 4].copy_from_slice(&1000u32.to_be_bytes());
+
+        let results: Vec<_> = [&farc[..], &bad_magic, &bad_count, &farc[len - 6..]].iter().enumerate()
+            .map(|(i, data)| {
+                let path = dir.join(format!("{i}.farc"));
+                fs::write(&path, data).unwrap();
+                GameData::default().index_farc(path)
+            })
+            .collect();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(results[0].is_ok());
+        for result in &results[1..] {
+            assert!(matches!(result, Err(GameDataError::InvalidFarc(_))));
+        }
+    }
+}
@@ -1,7 +1,26 @@
-use std::io::Write;
+use std::io::{Cursor, Write};
 
 use byteorder::{LittleEndian, WriteBytesExt};
-use anyhow::{anyhow, Result};
+use image::{DynamicImage, ImageFormat, ImageReader, Rgba, RgbaImage};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum GtfError {
+    #[error("invalid GTF texture pixel format: {0:#x}")]
+    InvalidFormat(u8),
+    #[error("unsupported GTF texture pixel format: {0}")]
+    UnsupportedFormat(&'static str),
+    #[error("unsupported GTF texture dimension: {0}")]
+    UnsupportedDimension(u8),
+    #[error("GTF texture data is truncated")]
+    Truncated,
+    #[error("image error: {0}")]
+    Image(#[from] image::ImageError),
+    #[error("io error: {0}")]
+    IOError(#[from] std::io::Error),
+}
+
+type Result<T> = std::result::Result<T, GtfError>;
 
 const DDS_HEADER_FLAGS_TEXTURE: u32 = 0x00001007;
 const DDS_HEADER_FLAGS_MIPMAP: u32 = 0x00020000;
@@ -23,9 +42,21 @@ const DDS_RGB: u32 = 0x40;
 const DDS_RGBA: u32 = 0x41;
 const DDS_LUMINANCE: u32 = 0x00020000;
 
+// flags stored in the upper bits of the GCM format byte
+pub const CELL_GCM_TEXTURE_LN: u8 = 0x20;
+pub const CELL_GCM_TEXTURE_UN: u8 = 0x40;
+
+const CELL_GCM_TEXTURE_REMAP_ZERO: u32 = 0;
+const CELL_GCM_TEXTURE_REMAP_ONE: u32 = 1;
+
+// cubemap faces start on 128 byte boundaries
+const CUBEMAP_FACE_ALIGNMENT: usize = 0x80;
+
 #[derive(Debug, PartialEq, Eq, Hash)]
 pub struct CellGcmTexture {
     pub format: CellGcmEnumForGtf,
+    // CELL_GCM_TEXTURE_LN, texture isn't swizzled
+    pub linear: bool,
     pub mipmap: u8,
     pub dimension: u8,
     pub cubemap: u8,
@@ -39,7 +70,7 @@ pub struct CellGcmTexture {
     pub offset: u32,
 }
 
-#[derive(Debug, PartialEq, Eq, Hash)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 pub enum CellGcmEnumForGtf {
     B8,
     A1R5G5B5,
@@ -50,12 +81,24 @@ pub enum CellGcmEnumForGtf {
     DXT3,
     DXT5,
     G8B8,
-    R5G5B5,
+    R6G5B5,
+    R5G5B5A1,
+    D1R5G5B5,
+    D8R8G8B8,
+    Depth24D8,
+    Depth16,
+    Depth16Float,
+    X16,
+    Y16X16,
+    W16Z16Y16X16Float,
+    W32Z32Y32X32Float,
+    X32Float,
+    Y16X16Float,
 }
 
 impl CellGcmEnumForGtf {
     pub fn from_u8(n: u8) -> Result<Self> {
-        Ok(match n {
+        Ok(match n & !(CELL_GCM_TEXTURE_LN | CELL_GCM_TEXTURE_UN) {
             0x81 => Self::B8,
             0x82 => Self::A1R5G5B5,
             0x83 => Self::A4R4G4B4,
@@ -65,12 +108,30 @@ impl CellGcmEnumForGtf {
             0x87 => Self::DXT3,
             0x88 => Self::DXT5,
             0x8b => Self::G8B8,
-            0x8f => Self::R5G5B5,
-            _ => return Err(anyhow!("Invalid GTF texture pixel format")),
+            0x8f => Self::R6G5B5,
+            0x97 => Self::R5G5B5A1,
+            0x9d => Self::D1R5G5B5,
+            0x9e => Self::D8R8G8B8,
+            0x90 => Self::Depth24D8,
+            0x92 => Self::Depth16,
+            0x93 => Self::Depth16Float,
+            0x94 => Self::X16,
+            0x95 => Self::Y16X16,
+            0x9a => Self::W16Z16Y16X16Float,
+            0x9b => Self::W32Z32Y32X32Float,
+            0x9c => Self::X32Float,
+            0x9f => Self::Y16X16Float,
+            // valid formats that we can't decode, named so the error says what they are
+            0x91 => return Err(GtfError::UnsupportedFormat("DEPTH24_D8_FLOAT")),
+            0x98 => return Err(GtfError::UnsupportedFormat("COMPRESSED_HILO8")),
+            0x99 => return Err(GtfError::UnsupportedFormat("COMPRESSED_HILO_S8")),
+            0xad => return Err(GtfError::UnsupportedFormat("COMPRESSED_B8R8_G8R8")),
+            0xae => return Err(GtfError::UnsupportedFormat("COMPRESSED_R8B8_R8G8")),
+            _ => return Err(GtfError::InvalidFormat(n)),
         })
     }
-    fn dds_pixelformat(&self) -> Result<[u32; 8]> {
-        Ok(match self {
+    fn dds_pixelformat(&self) -> [u32; 8] {
+        match self {
             Self::B8 =>       [0x20, DDS_LUMINANCE, 0, 8, 0, 0, 0x000000ff, 0],
             Self::A1R5G5B5 => [0x20, DDS_RGBA, 0, 16, 0x00007c00, 0x000003e0, 0x0000001f, 0x00008000],
             Self::A4R4G4B4 => [0x20, DDS_RGBA, 0, 16, 0x00000f00, 0x000000f0, 0x0000000f, 0x0000f000],
@@ -79,8 +140,353 @@ impl CellGcmEnumForGtf {
             Self::DXT1 =>     [0x20, DDS_FOURCC, 0x31545844, 0, 0, 0, 0, 0],
             Self::DXT3 =>     [0x20, DDS_FOURCC, 0x33545844, 0, 0, 0, 0, 0],
             Self::DXT5 =>     [0x20, DDS_FOURCC, 0x35545844, 0, 0, 0, 0, 0],
-            _ => return Err(anyhow!("Unimplemented DDS pixel format type")),
-        })
+            Self::G8B8 =>     [0x20, DDS_RGB, 0, 16, 0, 0x0000ff00, 0x000000ff, 0],
+            Self::R6G5B5 =>   [0x20, DDS_RGB, 0, 16, 0x0000fc00, 0x000003e0, 0x0000001f, 0x00000000],
+            Self::R5G5B5A1 => [0x20, DDS_RGBA, 0, 16, 0x0000f800, 0x000007c0, 0x0000003e, 0x00000001],
+            Self::D1R5G5B5 => [0x20, DDS_RGB, 0, 16, 0x00007c00, 0x000003e0, 0x0000001f, 0x00000000],
+            Self::D8R8G8B8 => [0x20, DDS_RGB, 0, 32, 0x00ff0000, 0x0000ff00, 0x000000ff, 0x00000000],
+            Self::Depth24D8 => [0x20, DDS_LUMINANCE, 0, 32, 0xffffff00, 0, 0, 0],
+            Self::Depth16 =>  [0x20, DDS_LUMINANCE, 0, 16, 0x0000ffff, 0, 0, 0],
+            Self::X16 =>      [0x20, DDS_LUMINANCE, 0, 16, 0x0000ffff, 0, 0, 0],
+            Self::Y16X16 =>   [0x20, DDS_RGB, 0, 32, 0x0000ffff, 0xffff0000, 0, 0],
+            // float formats use the D3DFMT values in place of a fourcc
+            Self::Depth16Float =>      [0x20, DDS_FOURCC, 111, 0, 0, 0, 0, 0],
+            Self::Y16X16Float =>       [0x20, DDS_FOURCC, 112, 0, 0, 0, 0, 0],
+            Self::W16Z16Y16X16Float => [0x20, DDS_FOURCC, 113, 0, 0, 0, 0, 0],
+            Self::X32Float =>          [0x20, DDS_FOURCC, 114, 0, 0, 0, 0, 0],
+            Self::W32Z32Y32X32Float => [0x20, DDS_FOURCC, 116, 0, 0, 0, 0, 0],
+        }
+    }
+
+    fn is_compressed(&self) -> bool {
+        matches!(self, Self::DXT1 | Self::DXT3 | Self::DXT5)
+    }
+
+    // bytes per texel, or bytes per 4x4 block for compressed formats
+    fn block_size(&self) -> usize {
+        match self {
+            Self::B8 => 1,
+            Self::A1R5G5B5 | Self::A4R4G4B4 | Self::R5G6B5 | Self::G8B8
+                | Self::R6G5B5 | Self::R5G5B5A1 | Self::D1R5G5B5
+                | Self::Depth16 | Self::Depth16Float | Self::X16 => 2,
+            Self::A8R8G8B8 | Self::D8R8G8B8 | Self::Depth24D8
+                | Self::Y16X16 | Self::X32Float | Self::Y16X16Float => 4,
+            Self::DXT1 | Self::W16Z16Y16X16Float => 8,
+            Self::DXT3 | Self::DXT5 | Self::W32Z32Y32X32Float => 16,
+        }
+    }
+
+    // reads a big endian texel into ARGB components, before remapping.
+    // wider formats are narrowed to 8 bits, components go to the ARGB slots in memory order
+    fn read_texel(&self, texel: &[u8]) -> [u8; 4] {
+        let short = || u16::from_be_bytes([texel[0], texel[1]]);
+        let half = |i: usize| float_to_u8(half_to_f32(u16::from_be_bytes([texel[i * 2], texel[i * 2 + 1]])));
+        let float = |i: usize| float_to_u8(f32::from_be_bytes([texel[i * 4], texel[i * 4 + 1], texel[i * 4 + 2], texel[i * 4 + 3]]));
+        match self {
+            Self::B8 => [texel[0]; 4],
+            Self::A1R5G5B5 | Self::D1R5G5B5 => {
+                let v = short();
+                let a = match self {
+                    Self::A1R5G5B5 => expand_bits(v >> 15, 1),
+                    _ => 0xff,
+                };
+                [a, expand_bits(v >> 10, 5), expand_bits(v >> 5, 5), expand_bits(v, 5)]
+            },
+            Self::A4R4G4B4 => {
+                let v = short();
+                [expand_bits(v >> 12, 4), expand_bits(v >> 8, 4), expand_bits(v >> 4, 4), expand_bits(v, 4)]
+            },
+            Self::R5G6B5 => {
+                let v = short();
+                [0xff, expand_bits(v >> 11, 5), expand_bits(v >> 5, 6), expand_bits(v, 5)]
+            },
+            Self::R6G5B5 => {
+                let v = short();
+                [0xff, expand_bits(v >> 10, 6), expand_bits(v >> 5, 5), expand_bits(v, 5)]
+            },
+            Self::R5G5B5A1 => {
+                let v = short();
+                [expand_bits(v, 1), expand_bits(v >> 11, 5), expand_bits(v >> 6, 5), expand_bits(v >> 1, 5)]
+            },
+            Self::A8R8G8B8 => [texel[0], texel[1], texel[2], texel[3]],
+            Self::D8R8G8B8 => [0xff, texel[1], texel[2], texel[3]],
+            // the RSX fetches G8B8 as GBGB
+            Self::G8B8 => [texel[0], texel[1], texel[0], texel[1]],
+            // only the top 8 bits of depth are kept, the stencil byte is dropped
+            Self::Depth24D8 | Self::Depth16 | Self::X16 => [texel[0]; 4],
+            Self::Depth16Float => [half(0); 4],
+            Self::Y16X16 => [texel[0], texel[2], texel[0], texel[2]],
+            Self::Y16X16Float => [half(0), half(1), half(0), half(1)],
+            Self::W16Z16Y16X16Float => [half(0), half(1), half(2), half(3)],
+            Self::X32Float => [float(0); 4],
+            Self::W32Z32Y32X32Float => [float(0), float(1), float(2), float(3)],
+            // compressed formats are decoded per block instead
+            Self::DXT1 | Self::DXT3 | Self::DXT5 => [0; 4],
+        }
+    }
+}
+
+fn expand_bits(value: u16, bits: u32) -> u8 {
+    let value = (value & ((1 << bits) - 1)) as u32;
+    ((value * 0xff + ((1 << bits) - 1) / 2) / ((1 << bits) - 1)) as u8
+}
+
+fn half_to_f32(half: u16) -> f32 {
+    let sign = match half & 0x8000 != 0 {
+        true => -1.0,
+        false => 1.0,
+    };
+    let exponent = ((half >> 10) & 0x1f) as i32;
+    let mantissa = (half & 0x3ff) as f32;
+    match exponent {
+        0 => sign * mantissa * 2f32.powi(-24),
+        0x1f if mantissa == 0.0 => sign * f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => sign * (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+// NaN ends up as 0
+fn float_to_u8(value: f32) -> u8 {
+    (value.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+fn rgb565_to_argb(color: u16) -> [u8; 4] {
+    [0xff, expand_bits(color >> 11, 5), expand_bits(color >> 5, 6), expand_bits(color, 5)]
+}
+
+fn lerp(a: u8, b: u8, num: u32, denom: u32) -> u8 {
+    ((a as u32 * (denom - num) + b as u32 * num) / denom) as u8
+}
+
+// decodes one 4x4 DXT block into ARGB texels, row by row
+fn decode_dxt_block(format: CellGcmEnumForGtf, block: &[u8]) -> [[u8; 4]; 16] {
+    let (alpha_block, color_block) = match format {
+        CellGcmEnumForGtf::DXT1 => (None, block),
+        _ => (Some(&block[..8]), &block[8..]),
+    };
+
+    let c0 = u16::from_le_bytes([color_block[0], color_block[1]]);
+    let c1 = u16::from_le_bytes([color_block[2], color_block[3]]);
+    let indices = u32::from_le_bytes([color_block[4], color_block[5], color_block[6], color_block[7]]);
+
+    let argb0 = rgb565_to_argb(c0);
+    let argb1 = rgb565_to_argb(c1);
+    let mut colors = [argb0, argb1, [0xff; 4], [0xff; 4]];
+    // only dxt1 has the 3 color + transparent mode
+    if c0 > c1 || format != CellGcmEnumForGtf::DXT1 {
+        for i in 1..4 {
+            colors[2][i] = lerp(argb0[i], argb1[i], 1, 3);
+            colors[3][i] = lerp(argb0[i], argb1[i], 2, 3);
+        }
+    } else {
+        for i in 1..4 {
+            colors[2][i] = lerp(argb0[i], argb1[i], 1, 2);
+        }
+        colors[3] = [0; 4];
+    }
+
+    let mut texels = [[0u8; 4]; 16];
+    for (i, texel) in texels.iter_mut().enumerate() {
+        *texel = colors[((indices >> (i * 2)) & 3) as usize];
+    }
+
+    match (format, alpha_block) {
+        (CellGcmEnumForGtf::DXT3, Some(alpha)) => {
+            let alpha = u64::from_le_bytes(alpha.try_into().unwrap_or_default());
+            for (i, texel) in texels.iter_mut().enumerate() {
+                texel[0] = expand_bits(((alpha >> (i * 4)) & 0xf) as u16, 4);
+            }
+        },
+        (CellGcmEnumForGtf::DXT5, Some(alpha)) => {
+            let (a0, a1) = (alpha[0], alpha[1]);
+            let mut alphas = [a0, a1, 0, 0, 0, 0, 0, 0xff];
+            if a0 > a1 {
+                for (i, value) in alphas.iter_mut().enumerate().skip(2) {
+                    *value = lerp(a0, a1, i as u32 - 1, 7);
+                }
+            } else {
+                for (i, value) in alphas.iter_mut().enumerate().take(6).skip(2) {
+                    *value = lerp(a0, a1, i as u32 - 1, 5);
+                }
+                alphas[6] = 0;
+            }
+
+            let mut bits = [0u8; 8];
+            bits[..6].copy_from_slice(&alpha[2..8]);
+            let indices = u64::from_le_bytes(bits);
+            for (i, texel) in texels.iter_mut().enumerate() {
+                texel[0] = alphas[((indices >> (i * 3)) & 7) as usize];
+            }
+        },
+        _ => {},
+    }
+
+    texels
+}
+
+// offset of a texel in a swizzled (morton order) texture, u bits come first
+fn swizzle_index(mut x: u32, mut y: u32, width: u32, height: u32) -> usize {
+    let (mut w, mut h) = (width, height);
+    let mut index = 0;
+    let mut shift = 0;
+    while w > 1 || h > 1 {
+        if w > 1 {
+            index |= ((x & 1) as usize) << shift;
+            x >>= 1;
+            w >>= 1;
+            shift += 1;
+        }
+        if h > 1 {
+            index |= ((y & 1) as usize) << shift;
+            y >>= 1;
+            h >>= 1;
+            shift += 1;
+        }
+    }
+    index
+}
+
+/// A single decoded mip level of a texture face
+pub struct GtfSurface {
+    pub face: u8,
+    pub level: u8,
+    pub image: RgbaImage,
+}
+
+impl CellGcmTexture {
+    pub fn get_face_count(&self) -> u8 {
+        match self.cubemap != 0 {
+            true => 6,
+            false => 1,
+        }
+    }
+
+    pub fn get_level_count(&self) -> u8 {
+        self.mipmap.max(1)
+    }
+
+    fn get_level_dimensions(&self, level: u8) -> (u32, u32) {
+        let width = (self.width as u32 >> level).max(1);
+        let height = (self.height as u32 >> level).max(1);
+        (width, height)
+    }
+
+    fn is_swizzled(&self) -> bool {
+        // compressed textures and non power of two textures can't be swizzled
+        !self.linear
+            && !self.format.is_compressed()
+            && (self.width as u32).is_power_of_two()
+            && (self.height as u32).is_power_of_two()
+    }
+
+    // bytes between two rows of texels (or rows of blocks for compressed formats)
+    fn get_row_pitch(&self, width: u32) -> usize {
+        let block_size = self.format.block_size();
+        if self.format.is_compressed() {
+            width.div_ceil(4) as usize * block_size
+        } else if self.linear && self.pitch != 0 {
+            // linear textures use the base level pitch for every level
+            self.pitch as usize
+        } else {
+            width as usize * block_size
+        }
+    }
+
+    fn get_level_size(&self, level: u8) -> usize {
+        let (width, height) = self.get_level_dimensions(level);
+        let rows = match self.format.is_compressed() {
+            true => height.div_ceil(4),
+            false => height,
+        };
+        self.get_row_pitch(width) * rows as usize
+    }
+
+    /// Applies the remap from the texture to ARGB components, giving RGBA
+    fn remap_texel(&self, argb: [u8; 4]) -> Rgba<u8> {
+        // a null remap would make everything transparent black, treat it as the default one
+        if self.remap & 0xff00 == 0 {
+            return Rgba([argb[1], argb[2], argb[3], argb[0]]);
+        }
+
+        let mut out = [0u8; 4]; // ARGB
+        for (i, component) in out.iter_mut().enumerate() {
+            let source = (self.remap >> (i * 2)) & 3;
+            *component = match (self.remap >> (8 + i * 2)) & 3 {
+                CELL_GCM_TEXTURE_REMAP_ZERO => 0,
+                CELL_GCM_TEXTURE_REMAP_ONE => 0xff,
+                _ => argb[source as usize],
+            };
+        }
+        Rgba([out[1], out[2], out[3], out[0]])
+    }
+
+    fn decode_level(&self, data: &[u8], level: u8) -> Result<RgbaImage> {
+        let (width, height) = self.get_level_dimensions(level);
+        let pitch = self.get_row_pitch(width);
+        let block_size = self.format.block_size();
+        let mut image = RgbaImage::new(width, height);
+
+        if self.format.is_compressed() {
+            for by in 0..height.div_ceil(4) {
+                for bx in 0..width.div_ceil(4) {
+                    let offset = by as usize * pitch + bx as usize * block_size;
+                    let block = data.get(offset..offset + block_size).ok_or(GtfError::Truncated)?;
+                    let texels = decode_dxt_block(self.format, block);
+                    for (i, texel) in texels.iter().enumerate() {
+                        let x = bx * 4 + i as u32 % 4;
+                        let y = by * 4 + i as u32 / 4;
+                        if x < width && y < height {
+                            image.put_pixel(x, y, self.remap_texel(*texel));
+                        }
+                    }
+                }
+            }
+            return Ok(image);
+        }
+
+        let swizzled = self.is_swizzled();
+        for y in 0..height {
+            for x in 0..width {
+                let offset = match swizzled {
+                    true => swizzle_index(x, y, width, height) * block_size,
+                    false => y as usize * pitch + x as usize * block_size,
+                };
+                let texel = data.get(offset..offset + block_size).ok_or(GtfError::Truncated)?;
+                image.put_pixel(x, y, self.remap_texel(self.format.read_texel(texel)));
+            }
+        }
+        Ok(image)
+    }
+
+    /// Decodes every mip level of every face, in the order they're stored
+    pub fn decode(&self, data: &[u8]) -> Result<Vec<GtfSurface>> {
+        if self.dimension == 3 {
+            return Err(GtfError::UnsupportedDimension(self.dimension));
+        }
+
+        let mut surfaces = Vec::new();
+        let mut offset = 0usize;
+        for face in 0..self.get_face_count() {
+            if face != 0 {
+                offset = offset.next_multiple_of(CUBEMAP_FACE_ALIGNMENT);
+            }
+            for level in 0..self.get_level_count() {
+                let level_data = data.get(offset..).ok_or(GtfError::Truncated)?;
+                let image = self.decode_level(level_data, level)?;
+                surfaces.push(GtfSurface { face, level, image });
+                offset += self.get_level_size(level);
+            }
+        }
+        Ok(surfaces)
+    }
+
+    /// Decodes only the first mip level of the first face
+    pub fn decode_first(&self, data: &[u8]) -> Result<RgbaImage> {
+        if self.dimension == 3 {
+            return Err(GtfError::UnsupportedDimension(self.dimension));
+        }
+        self.decode_level(data, 0)
     }
 }
 
@@ -103,7 +509,7 @@ pub fn make_dds_header(dds: &mut Vec<u8>, gcm: &CellGcmTexture) -> Result<()> {
     // dwReserved[11]
     dds.write_all(&[0u8; 11 * 4])?;
 
-    for value in gcm.format.dds_pixelformat()? {
+    for value in gcm.format.dds_pixelformat() {
         dds.write_u32::<LittleEndian>(value)?;
     }
 
@@ -137,4 +543,248 @@ pub fn make_dds_header(dds: &mut Vec<u8>, gcm: &CellGcmTexture) -> Result<()> {
     dds.write_all(&[0u8; 3 * 4])?;
     
     Ok(())
-}
\ No newline at end of file
+}
+
+fn argb_to_rgb565(argb: [u8; 4]) -> u16 {
+    ((argb[1] as u16 >> 3) << 11) | ((argb[2] as u16 >> 2) << 5) | (argb[3] as u16 >> 3)
+}
+
+fn color_distance(a: [u8; 4], b: [u8; 4]) -> u32 {
+    (1..4).map(|i| (a[i] as i32 - b[i] as i32).pow(2) as u32).sum()
+}
+
+// encodes one 4x4 block of ARGB texels, using the bounding box of the block as endpoints
+fn encode_dxt5_block(texels: &[[u8; 4]; 16]) -> [u8; 16] {
+    let mut block = [0u8; 16];
+
+    let a0 = texels.iter().map(|t| t[0]).max().unwrap_or(0xff);
+    let a1 = texels.iter().map(|t| t[0]).min().unwrap_or(0xff);
+    let mut alphas = [a0, a1, 0, 0, 0, 0, 0, 0];
+    for (i, value) in alphas.iter_mut().enumerate().skip(2) {
+        *value = lerp(a0, a1, i as u32 - 1, 7);
+    }
+    let mut alpha_indices = 0u64;
+    for (i, texel) in texels.iter().enumerate() {
+        let index = (0..8)
+            .min_by_key(|j| (alphas[*j] as i32 - texel[0] as i32).abs())
+            .unwrap_or(0) as u64;
+        alpha_indices |= index << (i * 3);
+    }
+    block[0] = a0;
+    block[1] = a1;
+    block[2..8].copy_from_slice(&alpha_indices.to_le_bytes()[..6]);
+
+    let mut max = [0xff, 0, 0, 0];
+    let mut min = [0xff, 0xff, 0xff, 0xff];
+    for texel in texels {
+        for i in 1..4 {
+            max[i] = max[i].max(texel[i]);
+            min[i] = min[i].min(texel[i]);
+        }
+    }
+    let c0 = argb_to_rgb565(max);
+    let c1 = argb_to_rgb565(min);
+    let argb0 = rgb565_to_argb(c0);
+    let argb1 = rgb565_to_argb(c1);
+    let mut colors = [argb0, argb1, [0xff; 4], [0xff; 4]];
+    for i in 1..4 {
+        colors[2][i] = lerp(argb0[i], argb1[i], 1, 3);
+        colors[3][i] = lerp(argb0[i], argb1[i], 2, 3);
+    }
+    let mut color_indices = 0u32;
+    for (i, texel) in texels.iter().enumerate() {
+        let index = (0..4)
+            .min_by_key(|j| color_distance(colors[*j], *texel))
+            .unwrap_or(0) as u32;
+        color_indices |= index << (i * 2);
+    }
+    block[8..10].copy_from_slice(&c0.to_le_bytes());
+    block[10..12].copy_from_slice(&c1.to_le_bytes());
+    block[12..16].copy_from_slice(&color_indices.to_le_bytes());
+
+    block
+}
+
+/// Compresses an image to DXT5 blocks, as stored after a DDS header
+pub fn encode_dxt5(img: &RgbaImage) -> Vec<u8> {
+    let (width, height) = img.dimensions();
+    let mut data = Vec::with_capacity((width.div_ceil(4) * height.div_ceil(4) * 16) as usize);
+
+    for by in 0..height.div_ceil(4) {
+        for bx in 0..width.div_ceil(4) {
+            let mut texels = [[0u8; 4]; 16];
+            for (i, texel) in texels.iter_mut().enumerate() {
+                // repeat the edge for blocks that go past the image
+                let x = (bx * 4 + i as u32 % 4).min(width - 1);
+                let y = (by * 4 + i as u32 / 4).min(height - 1);
+                let Rgba([r, g, b, a]) = *img.get_pixel(x, y);
+                *texel = [a, r, g, b];
+            }
+            data.extend_from_slice(&encode_dxt5_block(&texels));
+        }
+    }
+
+    data
+}
+
+/// Decodes the first surface of texture data from `ResrcMethod::Texture`,
+/// TEX resources are DDS files while GTF resources come with their GCM info
+pub fn decode_texture(data: &[u8], gcm_info: Option<&CellGcmTexture>) -> Result<DynamicImage> {
+    match gcm_info {
+        Some(gcm) => Ok(DynamicImage::ImageRgba8(gcm.decode_first(data)?)),
+        None => {
+            let mut img = ImageReader::new(Cursor::new(data));
+            img.set_format(ImageFormat::Dds);
+            Ok(img.decode()?)
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texture(format: CellGcmEnumForGtf, linear: bool, width: u16, height: u16, mipmap: u8, cubemap: u8) -> CellGcmTexture {
+        CellGcmTexture {
+            format,
+            linear,
+            mipmap,
+            dimension: 2,
+            cubemap,
+            remap: 0,
+            width,
+            height,
+            depth: 1,
+            location: 0,
+            flags: 0,
+            pitch: 0,
+            offset: 0,
+        }
+    }
+
+    #[test]
+    fn swizzle_order() {
+        // square textures are plain morton order
+        assert_eq!(swizzle_index(1, 0, 4, 4), 1);
+        assert_eq!(swizzle_index(0, 1, 4, 4), 2);
+        assert_eq!(swizzle_index(2, 0, 4, 4), 4);
+        assert_eq!(swizzle_index(0, 2, 4, 4), 8);
+        assert_eq!(swizzle_index(3, 3, 4, 4), 15);
+        // the longer side keeps going once the shorter one runs out of bits
+        assert_eq!(swizzle_index(2, 0, 4, 2), 4);
+        assert_eq!(swizzle_index(3, 1, 4, 2), 7);
+        assert_eq!(swizzle_index(0, 3, 2, 4), 6);
+    }
+
+    #[test]
+    fn unswizzle() {
+        let data: Vec<u8> = (0..16).collect();
+
+        let img = texture(CellGcmEnumForGtf::B8, false, 4, 4, 1, 0).decode_first(&data).unwrap();
+        for (x, y, pixel) in img.enumerate_pixels() {
+            assert_eq!(pixel.0, [swizzle_index(x, y, 4, 4) as u8; 4]);
+        }
+
+        // linear textures are read row by row
+        let img = texture(CellGcmEnumForGtf::B8, true, 4, 4, 1, 0).decode_first(&data).unwrap();
+        for (x, y, pixel) in img.enumerate_pixels() {
+            assert_eq!(pixel.0, [(y * 4 + x) as u8; 4]);
+        }
+    }
+
+    #[test]
+    fn dxt1_block() {
+        // red and blue endpoints, texels use indices 0, 1, 2, 3
+        let block = [0x00, 0xf8, 0x1f, 0x00, 0xe4, 0, 0, 0];
+        let texels = decode_dxt_block(CellGcmEnumForGtf::DXT1, &block);
+        assert_eq!(texels[0], [0xff, 0xff, 0, 0]);
+        assert_eq!(texels[1], [0xff, 0, 0, 0xff]);
+        assert_eq!(texels[2], [0xff, 170, 0, 85]);
+        assert_eq!(texels[3], [0xff, 85, 0, 170]);
+        assert_eq!(texels[4], texels[0]);
+
+        // swapped endpoints switch to 3 colors and transparent black
+        let block = [0x1f, 0x00, 0x00, 0xf8, 0xe4, 0, 0, 0];
+        let texels = decode_dxt_block(CellGcmEnumForGtf::DXT1, &block);
+        assert_eq!(texels[2], [0xff, 127, 0, 127]);
+        assert_eq!(texels[3], [0; 4]);
+    }
+
+    #[test]
+    fn dxt3_dxt5_alpha() {
+        let color = [0x00, 0xf8, 0x00, 0xf8, 0, 0, 0, 0];
+
+        let mut block = [0u8; 16];
+        block[0] = 0x8f;
+        block[8..].copy_from_slice(&color);
+        let texels = decode_dxt_block(CellGcmEnumForGtf::DXT3, &block);
+        assert_eq!(texels[0], [0xff, 0xff, 0, 0]);
+        assert_eq!(texels[1][0], 136);
+        assert_eq!(texels[2][0], 0);
+
+        // 8 alpha mode, texels use indices 0, 1, 2
+        let mut block = [0u8; 16];
+        block[..3].copy_from_slice(&[0xff, 0x00, 0x88]);
+        block[8..].copy_from_slice(&color);
+        let texels = decode_dxt_block(CellGcmEnumForGtf::DXT5, &block);
+        assert_eq!(texels[0][0], 0xff);
+        assert_eq!(texels[1][0], 0);
+        assert_eq!(texels[2][0], 218);
+    }
+
+    #[test]
+    fn dxt5_round_trip() {
+        let img = RgbaImage::from_pixel(6, 6, Rgba([0xff, 0, 0, 0x80]));
+        let data = encode_dxt5(&img);
+        assert_eq!(data.len(), 4 * 16);
+
+        let decoded = texture(CellGcmEnumForGtf::DXT5, false, 6, 6, 1, 0).decode_first(&data).unwrap();
+        assert_eq!(decoded, img);
+    }
+
+    #[test]
+    fn cubemap_surfaces() {
+        // 16 + 4 + 1 bytes per face, each face starting on a 128 byte boundary
+        let mut data = vec![0u8; 5 * CUBEMAP_FACE_ALIGNMENT + 21];
+        for face in 0..6 {
+            let start = face * CUBEMAP_FACE_ALIGNMENT;
+            data[start..start + 21].fill(face as u8);
+        }
+
+        let surfaces = texture(CellGcmEnumForGtf::B8, false, 4, 4, 3, 1).decode(&data).unwrap();
+        assert_eq!(surfaces.len(), 18);
+        for (i, surface) in surfaces.iter().enumerate() {
+            assert_eq!((surface.face, surface.level), (i as u8 / 3, i as u8 % 3));
+            assert_eq!(surface.image.width(), 4 >> surface.level);
+            assert!(surface.image.pixels().all(|p| p.0 == [surface.face; 4]));
+        }
+
+        assert!(matches!(
+            texture(CellGcmEnumForGtf::B8, false, 4, 4, 3, 1).decode(&data[..data.len() - 1]),
+            Err(GtfError::Truncated)
+        ));
+    }
+
+    #[test]
+    fn wide_formats() {
+        // 1.0, 0.5, 0.0 and -1.0 as halfs
+        let data = [0x3c, 0x00, 0x38, 0x00, 0x00, 0x00, 0xbc, 0x00];
+        let img = texture(CellGcmEnumForGtf::W16Z16Y16X16Float, true, 1, 1, 1, 0).decode_first(&data).unwrap();
+        assert_eq!(img.get_pixel(0, 0).0, [128, 0, 0, 0xff]);
+
+        let data = 0.25f32.to_be_bytes();
+        let img = texture(CellGcmEnumForGtf::X32Float, true, 1, 1, 1, 0).decode_first(&data).unwrap();
+        assert_eq!(img.get_pixel(0, 0).0, [64; 4]);
+
+        let data = [0x12, 0x34, 0xab, 0xcd];
+        let img = texture(CellGcmEnumForGtf::Y16X16, true, 1, 1, 1, 0).decode_first(&data).unwrap();
+        assert_eq!(img.get_pixel(0, 0).0, [0xab, 0x12, 0xab, 0x12]);
+    }
+
+    #[test]
+    fn unsupported_formats() {
+        assert_eq!(CellGcmEnumForGtf::from_u8(0x95 | CELL_GCM_TEXTURE_LN).unwrap(), CellGcmEnumForGtf::Y16X16);
+        assert!(matches!(CellGcmEnumForGtf::from_u8(0x98), Err(GtfError::UnsupportedFormat("COMPRESSED_HILO8"))));
+        assert!(matches!(CellGcmEnumForGtf::from_u8(0x80), Err(GtfError::InvalidFormat(0x80))));
+    }
+}
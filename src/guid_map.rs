@@ -0,0 +1,155 @@
+use std::{collections::HashMap, fs, io::{Cursor, Read, Seek, SeekFrom}, path::Path};
+
+use byteorder::{BigEndian, ReadBytesExt};
+use thiserror::Error;
+
+// map file format based on FileDB from cwlib
+// https://github.com/ennuo/toolkit/tree/main/lib/cwlib
+
+#[derive(Error, Debug)]
+pub enum GuidMapError {
+    #[error("Couldn't read GUID map {0}: {1}")]
+    Open(String, std::io::Error),
+    #[error("GUID map is truncated")]
+    Truncated,
+    #[error("io error: {0}")]
+    IOError(#[from] std::io::Error),
+}
+
+type Result<T> = std::result::Result<T, GuidMapError>;
+
+#[derive(Debug)]
+pub struct GuidMapEntry {
+    pub path: String,
+    pub size: u32,
+    pub sha1: [u8; 20],
+}
+
+#[derive(Debug, Default)]
+pub struct GuidMap {
+    entries: HashMap<u32, GuidMapEntry>,
+}
+
+impl GuidMap {
+    pub fn read(path: &Path) -> Result<Self> {
+        let data = fs::read(path).map_err(|e| GuidMapError::Open(path.display().to_string(), e))?;
+        Self::parse(&data)
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let mut map = Cursor::new(data);
+
+        let revision = map.read_u32::<BigEndian>()?;
+        let is_lbp3 = revision >> 0x10 >= 0x148;
+
+        let count = map.read_u32::<BigEndian>()?;
+
+        let mut entries = HashMap::new();
+        for _ in 0..count {
+            let path_len = match is_lbp3 {
+                true => map.read_u16::<BigEndian>()? as usize,
+                false => map.read_u32::<BigEndian>()? as usize,
+            };
+            let mut path = Vec::new();
+            (&mut map).take(path_len as u64).read_to_end(&mut path)?;
+            if path.len() != path_len {
+                return Err(GuidMapError::Truncated);
+            }
+
+            // timestamp, lbp1/lbp2 maps store it as 64-bit
+            map.seek(SeekFrom::Current(if is_lbp3 { 4 } else { 8 }))?;
+
+            let size = map.read_u32::<BigEndian>()?;
+            let mut sha1 = [0u8; 20];
+            map.read_exact(&mut sha1)?;
+            let guid = map.read_u32::<BigEndian>()?;
+
+            entries.insert(guid, GuidMapEntry {
+                path: String::from_utf8_lossy(&path).into_owned(),
+                size,
+                sha1,
+            });
+        }
+
+        Ok(Self { entries })
+    }
+
+    pub fn extend(&mut self, other: GuidMap) {
+        self.entries.extend(other.entries);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&u32, &GuidMapEntry)> {
+        self.entries.iter()
+    }
+
+    pub fn get(&self, guid: u32) -> Option<&GuidMapEntry> {
+        self.entries.get(&guid)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_map(is_lbp3: bool, entries: &[(&str, u32, [u8; 20], u32)]) -> Vec<u8> {
+        let mut map = Vec::new();
+        let revision: u32 = if is_lbp3 { 0x21a0000 } else { 0x100 };
+        map.extend(revision.to_be_bytes());
+        map.extend((entries.len() as u32).to_be_bytes());
+        for (path, size, sha1, guid) in entries {
+            match is_lbp3 {
+                true => map.extend((path.len() as u16).to_be_bytes()),
+                false => map.extend((path.len() as u32).to_be_bytes()),
+            }
+            map.extend(path.as_bytes());
+            map.extend(vec![0xaa; if is_lbp3 { 4 } else { 8 }]); // timestamp
+            map.extend(size.to_be_bytes());
+            map.extend(sha1);
+            map.extend(guid.to_be_bytes());
+        }
+        map
+    }
+
+    #[test]
+    fn parse_maps() {
+        let entries = [
+            ("gamedata/textures/a.tex", 100, [1; 20], 12345),
+            ("gamedata/levels/b.bin", 200, [2; 20], 3),
+        ];
+        for is_lbp3 in [false, true] {
+            let map = GuidMap::parse(&make_map(is_lbp3, &entries)).unwrap();
+            assert_eq!(map.len(), 2);
+            let entry = map.get(12345).unwrap();
+            assert_eq!(entry.path, "gamedata/textures/a.tex");
+            assert_eq!(entry.size, 100);
+            assert_eq!(entry.sha1, [1; 20]);
+            assert_eq!(map.get(3).unwrap().path, "gamedata/levels/b.bin");
+            assert!(map.get(4).is_none());
+        }
+    }
+
+    #[test]
+    fn truncated_maps() {
+        let map = make_map(false, &[("gamedata/textures/a.tex", 100, [1; 20], 12345)]);
+
+        // cut off in the middle of the path
+        assert!(matches!(GuidMap::parse(&map[..16]), Err(GuidMapError::Truncated)));
+        // cut off in the middle of the sha1
+        assert!(matches!(
+            GuidMap::parse(&map[..map.len() - 10]),
+            Err(GuidMapError::IOError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof,
+        ));
+        // more entries than there are
+        let mut map = map;
+        map[4..8].copy_from_slice(&2u32.to_be_bytes());
+        assert!(GuidMap::parse(&map).is_err());
+    }
+}
@@ -1,16 +1,46 @@
-use std::{collections::BTreeMap, fs::File, io::{Cursor, Write}, path::Path};
+use std::{fs::File, io::Write, path::Path};
 
-use crate::{gtf_texture::make_dds_header, resource_parse::{ResrcData, ResrcMethod}};
+use crate::{bitmap_font::{draw_text, text_width, transliterate, GLYPH_SIZE}, db::SlotInfo, gtf_texture::{decode_texture, encode_dxt5, make_dds_header, CellGcmEnumForGtf, CellGcmTexture, GtfError}, resource_parse::{compress_chunks, ResrcData, ResrcError, ResrcMethod}};
 
-use image::{imageops::FilterType, ImageReader, DynamicImage, ImageBuffer, ImageFormat, Rgba};
-use anyhow::Result;
+use image::{imageops::FilterType, DynamicImage, ImageBuffer, ImageError, ImageFormat, ImageReader, Rgba, RgbaImage};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum IconError {
+    #[error("Couldn't read image {0}: {1}")]
+    Open(String, ImageError),
+    #[error("image error: {0}")]
+    Image(#[from] ImageError),
+    #[error("resource error: {0}")]
+    Resrc(#[from] ResrcError),
+    #[error("texture error: {0}")]
+    Gtf(#[from] GtfError),
+    #[error("io error: {0}")]
+    IOError(#[from] std::io::Error),
+}
+
+type Result<T> = std::result::Result<T, IconError>;
 
 const MAX_WIDTH: u32 = 320;
 const MAX_HEIGHT: u32 = 176;
 
+// dark band at the bottom of generated placeholders, behind the level info
+const PLACEHOLDER_BAND_HEIGHT: u32 = 56;
+const PLACEHOLDER_MARGIN: u32 = 6;
+
+// backdrop shown behind the save in the save menu
+const PIC1_WIDTH: u32 = 1920;
+const PIC1_HEIGHT: u32 = 1080;
+const PIC1_BLUR_DOWNSCALE: u32 = 4;
+const PIC1_BLUR_SIGMA: f32 = 6.0;
+const PIC1_DARKEN: i32 = -70;
+
+// size of the in-game slot icon texture
+const SLOT_ICON_SIZE: u16 = 256;
+
 // code epically stolen from here :D
 // https://github.com/image-rs/image/issues/1701#issuecomment-1100276695
-fn img_resize_with_padding(img: DynamicImage) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+fn img_resize_with_padding(img: &DynamicImage) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
     let mut width = img.width();
     let mut height = img.height();
     let aspect_ratio = (width as f32) / (height as f32);
@@ -36,40 +66,164 @@ fn img_resize_with_padding(img: DynamicImage) -> ImageBuffer<Rgba<u8>, Vec<u8>>
     img
 }
 
-pub fn make_icon(bkp_path: &Path, icon_hash: Option<[u8; 20]>, hashes: &mut BTreeMap<[u8; 20], Vec<u8>>) -> Result<()> {
-    let mut icon_data = None;
-    let mut icon_gcm_info = None;
-
-    if let Some(hash) = icon_hash {
-        if let Some(icon_resrc) = hashes.get(&hash) {
-            let icon_resrc_id = ResrcData::new(icon_resrc, true)?;
-            if let ResrcMethod::Texture { data, gcm_info } = icon_resrc_id.method {
-                icon_data = Some(data);
-                icon_gcm_info = gcm_info;
-            }
+fn load_placeholder() -> Result<DynamicImage> {
+    Ok(image::load_from_memory_with_format(include_bytes!("assets/placeholder_icon.png"), ImageFormat::Png)?)
+}
+
+// shortens text with "..." so it fits in max_width
+fn fit_text(text: &str, max_width: u32, scale: u32) -> String {
+    if text_width(text, scale) <= max_width {
+        return text.to_string();
+    }
+    let max_chars = (max_width / (GLYPH_SIZE * scale)) as usize;
+    let mut fitted: String = text.chars().take(max_chars.saturating_sub(3)).collect();
+    fitted.push_str("...");
+    fitted
+}
+
+fn draw_text_with_shadow(img: &mut RgbaImage, text: &str, x: u32, y: u32, scale: u32) {
+    let (x, y) = (x as i64, y as i64);
+    draw_text(img, text, x + scale as i64, y + scale as i64, scale, Rgba([0, 0, 0, 255]));
+    draw_text(img, text, x, y, scale, Rgba([255, 255, 255, 255]));
+}
+
+/// Draws the level name, creator, game and level id over the placeholder icon,
+/// so levels without an icon can still be told apart in the save menu
+fn make_placeholder_icon(slot_info: &SlotInfo, level_id: Option<i64>) -> Result<RgbaImage> {
+    let mut img = img_resize_with_padding(&load_placeholder()?);
+
+    let band_y = MAX_HEIGHT - PLACEHOLDER_BAND_HEIGHT;
+    for y in band_y..MAX_HEIGHT {
+        for x in 0..MAX_WIDTH {
+            let [r, g, b, _] = img.get_pixel(x, y).0;
+            img.put_pixel(x, y, Rgba([r / 3, g / 3, b / 3, 255]));
         }
     }
 
-    let mut icon_file = File::create(bkp_path.join("ICON0.PNG"))?;
+    let max_width = MAX_WIDTH - PLACEHOLDER_MARGIN * 2;
+    // characters the font doesn't have are drawn as '?', so the level id below
+    // is what tells apart levels with names in other scripts
+    let name = transliterate(slot_info.get_display_name());
+    let name = name.as_str();
+    // long names are drawn at normal size instead of getting cut off early
+    let name_scale = match text_width(name, 2) <= max_width {
+        true => 2,
+        false => 1,
+    };
+
+    let mut y = band_y + PLACEHOLDER_MARGIN;
+    draw_text_with_shadow(&mut img, &fit_text(name, max_width, name_scale), PLACEHOLDER_MARGIN, y, name_scale);
+    y += GLYPH_SIZE * name_scale + 4;
+
+    let creator = format!("by {}", transliterate(&slot_info.np_handle));
+    draw_text_with_shadow(&mut img, &fit_text(&creator, max_width, 1), PLACEHOLDER_MARGIN, y, 1);
+    y += GLYPH_SIZE + 4;
+
+    let game = match level_id {
+        Some(level_id) => format!("{} #{level_id}", slot_info.game.get_short_title()),
+        None => slot_info.game.get_short_title().to_string(),
+    };
+    draw_text_with_shadow(&mut img, &game, PLACEHOLDER_MARGIN, y, 1);
+
+    Ok(img)
+}
 
-    match icon_data {
-        None => Ok(icon_file.write_all(include_bytes!("assets/placeholder_icon.png"))?),
-        Some(mut data) => {
-            if let Some(gcm_info) = icon_gcm_info {
-                let mut dds = Vec::with_capacity(0x80 + data.len());
-                make_dds_header(&mut dds, &gcm_info)?;
-                dds.write_all(&data)?;
-
-                data = dds;
-            }
-
-            let mut img = ImageReader::new(Cursor::new(data));
-            img.set_format(ImageFormat::Dds);
-            let img = img.decode()?;
-            let img = img_resize_with_padding(img);
-            img.write_to(&mut icon_file, ImageFormat::Png)?;
-            
+/// Reads a local image for the icon or backdrop, in any format the image crate can guess
+pub fn read_image(path: &Path) -> Result<DynamicImage> {
+    let open_err = |e| IconError::Open(path.display().to_string(), e);
+    ImageReader::open(path)
+        .and_then(|reader| reader.with_guessed_format())
+        .map_err(|e| open_err(ImageError::IoError(e)))?
+        .decode()
+        .map_err(open_err)
+}
+
+/// Decodes an icon texture resource, None if it isn't a texture
+pub fn decode_icon(icon_resrc: &[u8]) -> Result<Option<DynamicImage>> {
+    let icon_resrc_id = ResrcData::new(icon_resrc, true)?;
+    match icon_resrc_id.method {
+        ResrcMethod::Texture { data, gcm_info } => Ok(Some(decode_texture(&data, gcm_info.as_ref())?)),
+        _ => Ok(None),
+    }
+}
+
+pub fn make_icon(bkp_path: &Path, icon: Option<&DynamicImage>, slot_info: &SlotInfo, level_id: Option<i64>) -> Result<()> {
+    match icon {
+        None => {
+            let mut icon_file = File::create(bkp_path.join("ICON0.PNG"))?;
+            make_placeholder_icon(slot_info, level_id)?.write_to(&mut icon_file, ImageFormat::Png)?;
             Ok(())
-        }
+        },
+        Some(img) => write_icon(bkp_path, img),
     }
+}
+
+/// Writes an image as ICON0.PNG, padded to the icon size
+fn write_icon(bkp_path: &Path, img: &DynamicImage) -> Result<()> {
+    let mut icon_file = File::create(bkp_path.join("ICON0.PNG"))?;
+    let img = img_resize_with_padding(img);
+    img.write_to(&mut icon_file, ImageFormat::Png)?;
+    Ok(())
+}
+
+/// Makes a PIC1.PNG backdrop from the level icon, or the placeholder if there's none.
+/// The icon is blurred at a low resolution before scaling up, which is a lot cheaper
+/// than blurring the full size image and looks the same.
+pub fn make_pic1(bkp_path: &Path, icon: Option<&DynamicImage>) -> Result<()> {
+    let placeholder;
+    let icon = match icon {
+        Some(icon) => icon,
+        None => {
+            placeholder = load_placeholder()?;
+            &placeholder
+        },
+    };
+
+    let backdrop = icon
+        .resize_to_fill(PIC1_WIDTH / PIC1_BLUR_DOWNSCALE, PIC1_HEIGHT / PIC1_BLUR_DOWNSCALE, FilterType::Triangle)
+        .blur(PIC1_BLUR_SIGMA)
+        .brighten(PIC1_DARKEN)
+        .resize_exact(PIC1_WIDTH, PIC1_HEIGHT, FilterType::Triangle);
+
+    write_pic1(bkp_path, &backdrop)
+}
+
+/// Writes an image as PIC1.PNG, cropped to fill the backdrop size
+pub fn write_pic1(bkp_path: &Path, img: &DynamicImage) -> Result<()> {
+    let mut pic_file = File::create(bkp_path.join("PIC1.PNG"))?;
+    // it's a full screen backdrop, so there's nothing to show through transparent parts
+    let img = img.resize_to_fill(PIC1_WIDTH, PIC1_HEIGHT, FilterType::Triangle).to_rgb8();
+    img.write_to(&mut pic_file, ImageFormat::Png)?;
+    Ok(())
+}
+
+/// Encodes an image as a DXT5 TEX resource, for use as the in-game slot icon
+pub fn make_icon_texture(img: &DynamicImage) -> Result<Vec<u8>> {
+    let size = SLOT_ICON_SIZE as u32;
+    let img = img.resize_exact(size, size, FilterType::Triangle).to_rgba8();
+
+    let gcm = CellGcmTexture {
+        format: CellGcmEnumForGtf::DXT5,
+        linear: false,
+        mipmap: 1,
+        dimension: 2,
+        cubemap: 0,
+        remap: 0xaae4,
+        width: SLOT_ICON_SIZE,
+        height: SLOT_ICON_SIZE,
+        depth: 1,
+        location: 0,
+        flags: 0,
+        pitch: 0,
+        offset: 0,
+    };
+
+    let mut dds = Vec::new();
+    make_dds_header(&mut dds, &gcm)?;
+    dds.write_all(&encode_dxt5(&img))?;
+
+    let mut tex = Vec::new();
+    tex.write_all(b"TEX ")?;
+    tex.write_all(&compress_chunks(&dds))?;
+    Ok(tex)
 }
\ No newline at end of file
@@ -33,94 +33,107 @@ const fn lams(tag: &str) -> u32 {
     (result & 0xFFFFFFFF) as u32
 }
 
-pub const LABEL_LAMS_KEY_IDS: [u32; 85] = [
-    lams("LABEL_SinglePlayer"),
-    lams("LABEL_RPG"),
-    lams("LABEL_Multiplayer"),
-    lams("LABEL_SINGLE_PLAYER"),
-    lams("LABEL_Musical"),
-    lams("LABEL_Artistic"),
-    lams("LABEL_Funny"),
-    lams("LABEL_Scary"),
-    lams("LABEL_Easy"),
-    lams("LABEL_Challenging"),
-    lams("LABEL_Long"),
-    lams("LABEL_Quick"),
-    lams("LABEL_Time_Trial"),
-    lams("LABEL_Seasonal"),
-    lams("LABEL_16_Bit"),
-    lams("LABEL_8_Bit"),
-    lams("LABEL_Homage"),
-    lams("LABEL_Technology"),
-    lams("LABEL_Pinball"),
-    lams("LABEL_Movie"),
-    lams("LABEL_Sticker_Gallery"),
-    lams("LABEL_Costume_Gallery"),
-    lams("LABEL_Music_Gallery"),
-    lams("LABEL_Prop_Hunt"),
-    lams("LABEL_Hide_And_Seek"),
-    lams("LABEL_Hangout"),
-    lams("LABEL_Driving"),
-    lams("LABEL_Defence"),
-    lams("LABEL_Party_Game"),
-    lams("LABEL_Mini_Game"),
-    lams("LABEL_Card_Game"),
-    lams("LABEL_Board_Game"),
-    lams("LABEL_Arcade_Game"),
-    lams("LABEL_Social"),
-    lams("LABEL_Sci_Fi"),
-    lams("LABEL_3rd_Person"),
-    lams("LABEL_1st_Person"),
-    lams("LABEL_CO_OP"),
-    lams("LABEL_TOP_DOWN"),
-    lams("LABEL_Retro"),
-    lams("LABEL_Tutorial"),
-    lams("LABEL_SurvivalChallenge"),
-    lams("LABEL_Strategy"),
-    lams("LABEL_Story"),
-    lams("LABEL_Sports"),
-    lams("LABEL_Shooter"),
-    lams("LABEL_Race"),
-    lams("LABEL_Platform"),
-    lams("LABEL_Puzzle"),
-    lams("LABEL_Gallery"),
-    lams("LABEL_Fighter"),
-    lams("LABEL_Competitive"),
-    lams("LABEL_Cinematic"),
-    lams("LABEL_FLOATY_FLUID_NAME"),
-    lams("LABEL_HOVERBOARD_NAME"),
-    lams("LABEL_SPRINGINATOR"),
-    lams("LABEL_SACKPOCKET"),
-    lams("LABEL_QUESTS"),
-    lams("LABEL_INTERACTIVE_STREAM"),
-    lams("LABEL_WALLJUMP"),
-    lams("LABEL_MEMORISER"),
-    lams("LABEL_HEROCAPE"),
-    lams("LABEL_ATTRACT_TWEAK"),
-    lams("LABEL_ATTRACT_GEL"),
-    lams("LABEL_Paint"),
-    lams("LABEL_Movinator"),
-    lams("LABEL_Brain_Crane"),
-    lams("LABEL_Water"),
-    lams("LABEL_Vehicles"),
-    lams("LABEL_Sackbots"),
-    lams("LABEL_PowerGlove"),
-    lams("LABEL_Paintinator"),
-    lams("LABEL_LowGravity"),
-    lams("LABEL_MagicBag"),
-    lams("LABEL_JumpPads"),
-    lams("LABEL_GrapplingHook"),
-    lams("LABEL_Glitch"),
-    lams("LABEL_Explosives"),
-    lams("LABEL_DirectControl"),
-    lams("LABEL_Collectables"),
-    lams("LABEL_CREATED_CHARACTERS"),
-    lams("LABEL_SACKBOY"),
-    lams("LABEL_SWOOP"),
-    lams("LABEL_TOGGLE"),
-    lams("LABEL_ODDSOCK"),
+// in the same order as the authorLabels bits in the database
+const LABEL_TAGS: [&str; 85] = [
+    "LABEL_SinglePlayer",
+    "LABEL_RPG",
+    "LABEL_Multiplayer",
+    "LABEL_SINGLE_PLAYER",
+    "LABEL_Musical",
+    "LABEL_Artistic",
+    "LABEL_Funny",
+    "LABEL_Scary",
+    "LABEL_Easy",
+    "LABEL_Challenging",
+    "LABEL_Long",
+    "LABEL_Quick",
+    "LABEL_Time_Trial",
+    "LABEL_Seasonal",
+    "LABEL_16_Bit",
+    "LABEL_8_Bit",
+    "LABEL_Homage",
+    "LABEL_Technology",
+    "LABEL_Pinball",
+    "LABEL_Movie",
+    "LABEL_Sticker_Gallery",
+    "LABEL_Costume_Gallery",
+    "LABEL_Music_Gallery",
+    "LABEL_Prop_Hunt",
+    "LABEL_Hide_And_Seek",
+    "LABEL_Hangout",
+    "LABEL_Driving",
+    "LABEL_Defence",
+    "LABEL_Party_Game",
+    "LABEL_Mini_Game",
+    "LABEL_Card_Game",
+    "LABEL_Board_Game",
+    "LABEL_Arcade_Game",
+    "LABEL_Social",
+    "LABEL_Sci_Fi",
+    "LABEL_3rd_Person",
+    "LABEL_1st_Person",
+    "LABEL_CO_OP",
+    "LABEL_TOP_DOWN",
+    "LABEL_Retro",
+    "LABEL_Tutorial",
+    "LABEL_SurvivalChallenge",
+    "LABEL_Strategy",
+    "LABEL_Story",
+    "LABEL_Sports",
+    "LABEL_Shooter",
+    "LABEL_Race",
+    "LABEL_Platform",
+    "LABEL_Puzzle",
+    "LABEL_Gallery",
+    "LABEL_Fighter",
+    "LABEL_Competitive",
+    "LABEL_Cinematic",
+    "LABEL_FLOATY_FLUID_NAME",
+    "LABEL_HOVERBOARD_NAME",
+    "LABEL_SPRINGINATOR",
+    "LABEL_SACKPOCKET",
+    "LABEL_QUESTS",
+    "LABEL_INTERACTIVE_STREAM",
+    "LABEL_WALLJUMP",
+    "LABEL_MEMORISER",
+    "LABEL_HEROCAPE",
+    "LABEL_ATTRACT_TWEAK",
+    "LABEL_ATTRACT_GEL",
+    "LABEL_Paint",
+    "LABEL_Movinator",
+    "LABEL_Brain_Crane",
+    "LABEL_Water",
+    "LABEL_Vehicles",
+    "LABEL_Sackbots",
+    "LABEL_PowerGlove",
+    "LABEL_Paintinator",
+    "LABEL_LowGravity",
+    "LABEL_MagicBag",
+    "LABEL_JumpPads",
+    "LABEL_GrapplingHook",
+    "LABEL_Glitch",
+    "LABEL_Explosives",
+    "LABEL_DirectControl",
+    "LABEL_Collectables",
+    "LABEL_CREATED_CHARACTERS",
+    "LABEL_SACKBOY",
+    "LABEL_SWOOP",
+    "LABEL_TOGGLE",
+    "LABEL_ODDSOCK",
 ];
 
+const fn lams_all<const N: usize>(tags: &[&str; N]) -> [u32; N] {
+    let mut key_ids = [0; N];
+    let mut i = 0;
+    while i < N {
+        key_ids[i] = lams(tags[i]);
+        i += 1;
+    }
+    key_ids
+}
+
+pub const LABEL_LAMS_KEY_IDS: [u32; 85] = lams_all(&LABEL_TAGS);
+
 pub const LBP2_LABELS: [u32; 46] = [
     lams("LABEL_SinglePlayer"),
     lams("LABEL_Multiplayer"),
@@ -168,4 +181,20 @@ pub const LBP2_LABELS: [u32; 46] = [
     lams("LABEL_HEROCAPE"),
     lams("LABEL_MEMORISER"),
     lams("LABEL_WALLJUMP"),
-];
\ No newline at end of file
+];
+
+/// Key ID of an author label from its tag, with or without the LABEL_ prefix (Funny, LABEL_Time_Trial)
+pub fn get_label_key_id(tag: &str) -> Option<u32> {
+    let tag = match tag.starts_with("LABEL_") {
+        true => tag.to_string(),
+        false => format!("LABEL_{tag}"),
+    };
+    let key_id = lams(&tag);
+    LABEL_LAMS_KEY_IDS.contains(&key_id).then_some(key_id)
+}
+
+/// Tag of an author label without the LABEL_ prefix, for display
+pub fn get_label_name(key_id: u32) -> Option<&'static str> {
+    let i = LABEL_LAMS_KEY_IDS.iter().position(|k| *k == key_id)?;
+    Some(LABEL_TAGS[i].trim_start_matches("LABEL_"))
+}
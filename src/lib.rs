@@ -0,0 +1,21 @@
+pub mod resource_parse;
+pub mod resource_type;
+pub mod resource_stats;
+pub mod resource_dl;
+pub mod serializers;
+pub mod xxtea;
+pub mod labels;
+pub mod db;
+pub mod config;
+pub mod icon;
+pub mod bitmap_font;
+pub mod gtf_texture;
+pub mod guid_map;
+pub mod game_data;
+pub mod texture_export;
+pub mod pack;
+pub mod detail;
+
+pub static USER_AGENT: &str = concat!(
+    "lbp_archive_dl/", env!("CARGO_PKG_VERSION"),
+);
@@ -1,29 +1,26 @@
-use std::{fs, io::{stdout, Write}};
-use clap::{Parser, Subcommand};
-use config::Config;
-use icon::make_icon;
+use std::{collections::BTreeMap, fs, io::{stdout, Write}, path::PathBuf, process::ExitCode, sync::Arc};
+use clap::{error::ErrorKind, Args, CommandFactory, Parser, Subcommand};
 use sha1::{Digest, Sha1};
-use anyhow::{anyhow, Result};
-
-mod resource_parse;
-mod resource_dl;
-mod serializers;
-mod xxtea;
-mod labels;
-mod db;
-mod config;
-mod icon;
-mod gtf_texture;
-
-use serializers::lbp::{make_slotlist, make_savearchive};
-use serializers::ps3::{make_sfo, make_pfd};
-use db::{get_slot_info, GameVersion};
-use resource_parse::{ResrcDescriptor, ResrcData, ResrcMethod, ResrcRevision};
-use resource_dl::{download_level, DownloadResult};
-
-static USER_AGENT: &str = concat!(
-    "lbp_archive_dl/", env!("CARGO_PKG_VERSION"),
-);
+use anyhow::Result;
+use image::DynamicImage;
+
+use archive_dl::config::{Config, ConfigError};
+use archive_dl::icon::{decode_icon, make_icon, make_icon_texture, make_pic1, read_image, write_pic1, IconError};
+use archive_dl::texture_export::{export_textures, ExportError, ExportStats};
+use archive_dl::gtf_texture::GtfError;
+use archive_dl::guid_map::{GuidMap, GuidMapError};
+use archive_dl::game_data::{GameData, GameDataError};
+use archive_dl::serializers::SerializeError;
+use archive_dl::serializers::lbp::{make_slotlist, make_savearchive};
+use archive_dl::serializers::ps3::{make_sfo, make_pfd, read_pfd, PfdInfo, Sfo, SfoValue};
+use archive_dl::db::{get_slot_info, is_valid_titleid, DbError, GameVersion, LevelType, Region, SlotInfo};
+use archive_dl::labels::get_label_key_id;
+use archive_dl::resource_parse::{ResrcDescriptor, ResrcData, ResrcError, ResrcMethod, ResrcRevision};
+use archive_dl::resource_dl::{download_level, DownloadError, DownloadResult, TypeFilter};
+use archive_dl::resource_type::ResourceType;
+use archive_dl::resource_stats::ResourceStats;
+use archive_dl::detail::{make_detail, Provenance};
+use archive_dl::pack::{read_resources, read_slot_file, retain_reachable, LocalLevel, PackError};
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
@@ -33,76 +30,546 @@ struct Cli {
     command: Commands,
 }
 
+#[derive(Args)]
+struct FilterArgs {
+    /// Only download dependencies of these types (for example plan,texture)
+    #[arg(long, value_delimiter = ',')]
+    include_types: Vec<ResourceType>,
+    /// Don't download dependencies of these types (for example sample,instrument)
+    #[arg(long, value_delimiter = ',')]
+    exclude_types: Vec<ResourceType>,
+}
+
+impl From<FilterArgs> for TypeFilter {
+    fn from(args: FilterArgs) -> Self {
+        Self {
+            include: args.include_types,
+            exclude: args.exclude_types,
+        }
+    }
+}
+
+/// Options for writing a level backup, shared by bkp and pack
+#[derive(Args)]
+struct BackupArgs {
+    /// Force LBP3 backup
+    #[arg(short, long)]
+    lbp3: bool,
+    /// Use a local image (PNG/JPEG) as the backup icon
+    #[arg(long)]
+    icon: Option<PathBuf>,
+    /// Also use the local image as the in-game slot icon
+    #[arg(long, requires = "icon")]
+    replace_slot_icon: bool,
+    /// Generate a PIC1.PNG save menu backdrop from the level icon
+    #[arg(long)]
+    pic1: bool,
+    /// Use a local image (PNG/JPEG) as the PIC1.PNG backdrop
+    #[arg(long)]
+    pic1_image: Option<PathBuf>,
+    /// Revision to write the backup with, either a known one
+    /// (lbp1-unbranched, lbp1, lbp2-production, lbp2, lbp3) or hex like 0x3f8 or 0x272:0x4c44:0x17
+    #[arg(long, conflicts_with_all = ["lbp3", "upgrade"])]
+    revision: Option<ResrcRevision>,
+    /// Upgrade the backup to a newer game if some resources need it
+    #[arg(long)]
+    upgrade: bool,
+    /// Region of the game release to make the backup for
+    /// (bces, bcus, bcjs, bcas, npua, npea)
+    #[arg(long, conflicts_with = "title_id")]
+    region: Option<Region>,
+    /// Title ID to make the backup for (for example BCUS98245), overrides the region
+    #[arg(long, value_parser = parse_titleid)]
+    title_id: Option<String>,
+    /// Print statistics about the level's resources
+    #[arg(long)]
+    stats: bool,
+}
+
+/// Slot fields to replace before the backup is written
+#[derive(Args)]
+struct SlotArgs {
+    /// Replace the level's name
+    #[arg(long)]
+    name: Option<String>,
+    /// Replace the level's description
+    #[arg(long)]
+    description: Option<String>,
+    /// Replace the creator's PSN name
+    #[arg(long, value_parser = parse_creator)]
+    creator: Option<String>,
+    /// Replace the author labels (for example Funny,Quick)
+    #[arg(long, value_delimiter = ',', value_parser = parse_label)]
+    labels: Option<Vec<u32>>,
+    /// Remove all author labels
+    #[arg(long, conflicts_with = "labels")]
+    clear_labels: bool,
+    /// Replace the level type (cooperative, versus, cutscene)
+    #[arg(long)]
+    level_type: Option<LevelType>,
+    /// Replace the minimum player count
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..=4))]
+    min_players: Option<u8>,
+    /// Replace the maximum player count
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..=4))]
+    max_players: Option<u8>,
+    /// Whether the level is locked until it's unlocked by another level
+    #[arg(long)]
+    initially_locked: Option<bool>,
+    /// Whether the level can be copied by other players
+    #[arg(long)]
+    shareable: Option<bool>,
+}
+
+fn parse_sfo_field(field: &str) -> std::result::Result<(String, String), String> {
+    field.split_once('=')
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .ok_or_else(|| format!("{field} should be in the form KEY=VALUE"))
+}
+
+fn parse_creator(creator: &str) -> std::result::Result<String, String> {
+    match creator.len() {
+        0..=16 => Ok(creator.to_string()),
+        _ => Err(format!("{creator} is longer than 16 bytes")),
+    }
+}
+
+fn parse_label(label: &str) -> std::result::Result<u32, String> {
+    get_label_key_id(label).ok_or_else(|| format!("unknown author label: {label}"))
+}
+
+impl SlotArgs {
+    // clap can't compare two arguments, so this exits like a parsing error would
+    fn check(&self) {
+        if let (Some(min_players), Some(max_players)) = (self.min_players, self.max_players) && min_players > max_players {
+            Cli::command()
+                .error(ErrorKind::ArgumentConflict, format!("--min-players {min_players} is more than --max-players {max_players}"))
+                .exit();
+        }
+    }
+
+    fn apply(self, slot_info: &mut SlotInfo) {
+        if let Some(name) = self.name {
+            slot_info.name = name;
+        }
+        if let Some(description) = self.description {
+            slot_info.description = description;
+        }
+        if let Some(creator) = self.creator {
+            slot_info.np_handle = creator;
+        }
+        if let Some(labels) = self.labels {
+            slot_info.author_labels = labels;
+        } else if self.clear_labels {
+            slot_info.author_labels.clear();
+        }
+        if let Some(leveltype) = self.level_type {
+            slot_info.leveltype = leveltype;
+        }
+        if let Some(min_players) = self.min_players {
+            slot_info.min_players = Some(min_players);
+        }
+        if let Some(max_players) = self.max_players {
+            slot_info.max_players = Some(max_players);
+        }
+        if let Some(initially_locked) = self.initially_locked {
+            slot_info.initially_locked = initially_locked;
+        }
+        if let Some(shareable) = self.shareable {
+            slot_info.shareable = shareable;
+        }
+
+        // one of the counts can still conflict with the level's own
+        let min_players = slot_info.min_players.unwrap_or(1);
+        let max_players = slot_info.max_players.unwrap_or(4);
+        if min_players > max_players {
+            Cli::command()
+                .error(ErrorKind::ArgumentConflict, format!("the level would have {min_players} minimum players but only {max_players} maximum players"))
+                .exit();
+        }
+    }
+}
+
 #[derive(Subcommand)]
 enum Commands {
     /// Download level and save as level backup
     Bkp {
         /// Level ID from database
         level_id: i64,
-        /// Force LBP3 backup
+        #[command(flatten)]
+        backup: BackupArgs,
+        #[command(flatten)]
+        slot: SlotArgs,
+        #[command(flatten)]
+        filter: FilterArgs,
+    },
+    /// Make a level backup from local files instead of the database
+    Pack {
+        /// Root level resource
+        root_level: PathBuf,
+        /// YAML or JSON file with the slot's name, description, creator and so on
         #[arg(short, long)]
-        lbp3: bool,
-    }
+        slot: PathBuf,
+        /// Directory with the level's dependencies, files are found by their SHA1
+        #[arg(short, long)]
+        dependencies: Option<PathBuf>,
+        #[command(flatten)]
+        backup: BackupArgs,
+    },
+    /// Print or edit a backup's PARAM.SFO, PARAM.PFD is signed again after editing if it only protects PARAM.SFO
+    Sfo {
+        /// Backup directory
+        dir: PathBuf,
+        /// Set a field as KEY=VALUE, integers can be decimal or 0x hex and arrays are hex.
+        /// Values have to fit in the space an existing field already has
+        #[arg(long, value_parser = parse_sfo_field)]
+        set: Vec<(String, String)>,
+        /// Remove a field
+        #[arg(long)]
+        remove: Vec<String>,
+    },
+    /// Download level and export its textures as PNG files
+    Textures {
+        /// Level ID from database
+        level_id: i64,
+        /// Directory where a folder with the textures is created
+        #[arg(short, long, default_value = "textures")]
+        output: PathBuf,
+        #[command(flatten)]
+        filter: FilterArgs,
+    },
+    /// Download level and print statistics about its resources
+    Stats {
+        /// Level ID from database
+        level_id: i64,
+        #[command(flatten)]
+        filter: FilterArgs,
+    },
 }
 
-async fn dl_as_backup(level_id: i64, config: Config, force_lbp3: bool) -> Result<()> {
-    let slot_info = get_slot_info(level_id, &config.database_path)?;
+struct LevelDownload {
+    slot_info: SlotInfo,
+    resources: BTreeMap<[u8; 20], Vec<u8>>,
+    icon_sha1: Option<[u8; 20]>,
+    // icon read from the game data, if it's a built-in one
+    local_icon: Option<Vec<u8>>,
+    // true if some dependencies were left out by the type filter
+    is_partial: bool,
+    // resources that couldn't be downloaded
+    missing: usize,
+    // dependencies that weren't downloaded since they're in the game data, with their types
+    skipped: BTreeMap<[u8; 20], Option<ResourceType>>,
+}
 
-    let display_name = if !slot_info.name.is_empty() {
-        slot_info.name.as_str()
-    } else {
-        "Unnamed Level"
-    };
+// slot overrides are applied before any resources are downloaded, so bad ones fail early
+async fn download_slot(level_id: i64, config: &Config, filter: TypeFilter, slot: Option<SlotArgs>) -> Result<LevelDownload> {
+    let mut slot_info = get_slot_info(level_id, &config.database_path)?;
+    if let Some(slot) = slot {
+        slot.apply(&mut slot_info);
+    }
 
     println!("Level found!");
-    println!("Name: {display_name}");
+    println!("Name: {}", slot_info.get_display_name());
     println!("Creator: {}", slot_info.np_handle);
     println!("Game: {}", slot_info.game.get_short_title());
-
     let mut max_parallel_downloads = config.max_parallel_downloads;
     if max_parallel_downloads > 10 {
         eprintln!("WARNING: max_parallel_downloads is too high, reverting to 10");
         max_parallel_downloads = 10;
     } else if max_parallel_downloads == 0 {
-        return Err(anyhow!("max_parallel_downloads cannot be set to zero"));
+        return Err(ConfigError::Zero("max_parallel_downloads").into());
     }
 
-    print!("Downloading resources");
-    stdout().flush()?;
+    let guid_map = match &config.guid_map_path {
+        Some(path) => {
+            let guid_map = GuidMap::read(path)?;
+            println!("{} GUIDs loaded from GUID map", guid_map.len());
+            guid_map
+        },
+        None => GuidMap::default(),
+    };
+
+    let mut game_data = GameData::new(guid_map);
+    if let Some(dir) = &config.game_data_directory {
+        game_data.index_directory(dir)?;
+        println!("{} resources indexed from game data", game_data.len());
+    }
+
+    let icon_sha1 = match slot_info.icon {
+        ResrcDescriptor::Sha1(icon_hash) => Some(icon_hash),
+        ResrcDescriptor::Guid(0) => None,
+        ResrcDescriptor::Guid(guid) => {
+            let icon_sha1 = game_data.guid_map.get(guid).map(|entry| entry.sha1);
+            if icon_sha1.is_none() {
+                eprintln!("WARNING: Icon GUID g{guid} couldn't be resolved");
+            }
+            icon_sha1
+        },
+    };
 
-    let mut icon_sha1 = None;
-    if let ResrcDescriptor::Sha1(icon_hash) = slot_info.icon {
-        icon_sha1 = Some(icon_hash);
+    // built-in icons can be read straight from the game data instead of being downloaded
+    let mut local_icon = None;
+    if let Some(icon_hash) = icon_sha1 {
+        local_icon = game_data.read(&icon_hash)?;
     }
 
+    print!("Downloading resources");
+    stdout().flush()?;
+
     let DownloadResult {
-        mut resources,
+        resources,
         success_count: dl_count,
         error_count: fail_count,
+        resolved_guids,
+        unresolved_guids,
+        skipped,
+        skipped_size,
+        filtered,
     } = download_level(
         slot_info.root_level,
-        icon_sha1,
-        config.download_server,
+        icon_sha1.filter(|_| local_icon.is_none()),
+        config.download_server.clone(),
         max_parallel_downloads,
+        Arc::new(game_data),
+        filter,
     ).await?;
 
     println!();
 
-    let root_resrc = resources.get(&slot_info.root_level)
-        .ok_or(anyhow!("rootLevel is missing from the archive, rip"))?;
+    if !resources.contains_key(&slot_info.root_level) {
+        return Err(DownloadError::RootMissing.into());
+    }
 
     println!("Done!");
     println!("{dl_count} resources downloaded, {fail_count} failed");
+    if !skipped.is_empty() {
+        println!(
+            "{} resources skipped since they're in the game data, {:.2} MB saved",
+            skipped.len(),
+            skipped_size as f64 / (1024.0 * 1024.0),
+        );
+    }
+    if !resolved_guids.is_empty() {
+        println!("{} GUID resources resolved", resolved_guids.len());
+    }
+    if !unresolved_guids.is_empty() {
+        if config.guid_map_path.is_none() && config.game_data_directory.is_none() {
+            eprintln!("WARNING: {} GUID resources couldn't be resolved, set guid_map_path or game_data_directory in config.yml to resolve them", unresolved_guids.len());
+        } else {
+            eprintln!("WARNING: {} GUID resources couldn't be resolved:", unresolved_guids.len());
+            for guid in unresolved_guids {
+                eprintln!("  g{guid}");
+            }
+        }
+    }
+
+    if !filtered.is_empty() {
+        eprintln!("WARNING: {} dependencies were filtered out by type, this download is partial:", filtered.len());
+        for desc in &filtered {
+            match desc {
+                ResrcDescriptor::Sha1(sha1) => eprintln!("  {}", hex::encode(sha1)),
+                ResrcDescriptor::Guid(guid) => eprintln!("  g{guid}"),
+            }
+        }
+    }
+
+    Ok(LevelDownload {
+        slot_info,
+        resources,
+        icon_sha1,
+        local_icon,
+        is_partial: !filtered.is_empty(),
+        missing: fail_count,
+        skipped,
+    })
+}
+
+struct BackupOptions {
+    revision: Option<ResrcRevision>,
+    force_lbp3: bool,
+    stats: bool,
+    upgrade: bool,
+    icon: Option<PathBuf>,
+    replace_slot_icon: bool,
+    pic1: bool,
+    pic1_image: Option<PathBuf>,
+    region: Region,
+    title_id: Option<String>,
+}
+
+impl BackupOptions {
+    fn new(args: BackupArgs, config: &Config) -> Self {
+        Self {
+            revision: args.revision,
+            force_lbp3: args.lbp3 || config.force_lbp3_backups,
+            stats: args.stats,
+            upgrade: args.upgrade,
+            icon: args.icon,
+            replace_slot_icon: args.replace_slot_icon,
+            pic1: args.pic1 || config.generate_pic1,
+            pic1_image: args.pic1_image,
+            // --region on the command line replaces title_id from config.yml
+            region: args.region.unwrap_or(config.region),
+            title_id: args.title_id.or_else(|| args.region.is_none().then(|| config.title_id.clone()).flatten()),
+        }
+    }
+}
+
+fn parse_titleid(titleid: &str) -> std::result::Result<String, String> {
+    match is_valid_titleid(titleid) {
+        true => Ok(titleid.to_string()),
+        false => Err(format!("{titleid} is not a valid title ID, it should look like BCES01663")),
+    }
+}
+
+fn get_titleid(gameversion: GameVersion, region: Region, titleid: Option<&str>) -> Result<String> {
+    if let Some(titleid) = titleid {
+        if !is_valid_titleid(titleid) {
+            return Err(ConfigError::InvalidTitleId(titleid.to_string()).into());
+        }
+        return Ok(titleid.to_string());
+    }
+    gameversion.get_titleid(region)
+        .map(str::to_string)
+        .ok_or_else(|| ConfigError::UnknownTitleId(gameversion.get_short_title(), region.get_name()).into())
+}
+
+// binary resources saved by a newer revision than the target can read, newest first
+fn get_newer_resources(resources: &BTreeMap<[u8; 20], Vec<u8>>, target: &ResrcRevision) -> Vec<([u8; 20], [u8; 3], ResrcRevision)> {
+    let mut newer: Vec<_> = resources.iter()
+        .filter_map(|(sha1, resource)| match ResrcData::new(resource, false) {
+            Ok(ResrcData { resrc_type, method: ResrcMethod::Binary { revision, .. } }) if revision.is_newer_than(target) => {
+                Some((*sha1, resrc_type, revision))
+            },
+            _ => None,
+        })
+        .collect();
+    newer.sort_by_key(|(_, _, revision)| std::cmp::Reverse(*revision));
+    newer
+}
+
+async fn dl_as_backup(level_id: i64, config: Config, filter: TypeFilter, options: BackupOptions, slot: SlotArgs) -> Result<()> {
+    // read the custom images first, so a bad path doesn't waste a download
+    let custom_icon = options.icon.as_deref().map(read_image).transpose()?;
+    let custom_pic1 = options.pic1_image.as_deref().map(read_image).transpose()?;
+
+    let download = download_slot(level_id, &config, filter, Some(slot)).await?;
 
+    let provenance = Provenance {
+        level_id: Some(level_id),
+        source: config.download_server.get_name(),
+        missing: download.missing,
+    };
+    write_backup(level_id as u32, download, &provenance, &config, &options, custom_icon, custom_pic1)
+}
+
+fn pack_backup(root_path: PathBuf, slot_path: PathBuf, dependency_dir: Option<PathBuf>, config: Config, options: BackupOptions) -> Result<()> {
+    let custom_icon = options.icon.as_deref().map(read_image).transpose()?;
+    let custom_pic1 = options.pic1_image.as_deref().map(read_image).transpose()?;
+
+    let LocalLevel { root_level: root_sha1, mut resources } = read_resources(&root_path, dependency_dir.as_deref())?;
+    let root_resrc = ResrcData::new(&resources[&root_sha1], false)?;
+    let game = match root_resrc.method {
+        ResrcMethod::Binary { revision, .. } => revision.get_gameversion(),
+        _ => return Err(ResrcError::NonBinaryRoot.into())
+    };
+    let slot_info = read_slot_file(&slot_path, root_sha1, game)?;
+
+    println!("Name: {}", slot_info.get_display_name());
+    println!("Creator: {}", slot_info.np_handle);
+    println!("Game: {}", slot_info.game.get_short_title());
+
+    let icon_sha1 = match slot_info.icon {
+        ResrcDescriptor::Sha1(sha1) => Some(sha1),
+        ResrcDescriptor::Guid(_) => None,
+    };
+    if let Some(icon_sha1) = icon_sha1 && !resources.contains_key(&icon_sha1) {
+        return Err(PackError::IconMissing(hex::encode(icon_sha1)).into());
+    }
+
+    let file_count = resources.len();
+    let roots: Vec<[u8; 20]> = [root_sha1].into_iter().chain(icon_sha1).collect();
+    let missing = retain_reachable(&roots, &mut resources);
+    println!("{} resources read", resources.len());
+    if resources.len() < file_count {
+        println!("{} files aren't used by the level and were left out", file_count - resources.len());
+    }
+    if !missing.is_empty() {
+        eprintln!("WARNING: {} dependencies are missing from the dependency directory:", missing.len());
+        for sha1 in &missing {
+            eprintln!("  {}", hex::encode(sha1));
+        }
+    }
+
+    let download = LevelDownload {
+        slot_info,
+        resources,
+        icon_sha1,
+        local_icon: None,
+        is_partial: false,
+        missing: missing.len(),
+        skipped: BTreeMap::new(),
+    };
+    let provenance = Provenance {
+        level_id: None,
+        source: "local files",
+        missing: missing.len(),
+    };
+
+    // there's no level id, so the root level's hash keeps backups of different levels apart
+    let slot_id = u32::from_be_bytes(root_sha1[..4].try_into()?);
+    write_backup(slot_id, download, &provenance, &config, &options, custom_icon, custom_pic1)
+}
+
+fn write_backup(
+    slot_id: u32,
+    download: LevelDownload,
+    provenance: &Provenance,
+    config: &Config,
+    options: &BackupOptions,
+    custom_icon: Option<DynamicImage>,
+    custom_pic1: Option<DynamicImage>,
+) -> Result<()> {
+    let LevelDownload {
+        mut slot_info,
+        mut resources,
+        icon_sha1,
+        local_icon,
+        is_partial,
+        ..
+    } = download;
+
+    if options.stats {
+        print!("{}", ResourceStats::new(&resources));
+    }
+
+    if options.replace_slot_icon && let Some(img) = &custom_icon {
+        let tex = make_icon_texture(img)?;
+        let tex_hash = Sha1::digest(&tex).into();
+        resources.insert(tex_hash, tex);
+        slot_info.icon = ResrcDescriptor::Sha1(tex_hash);
+    }
+    let display_name = slot_info.get_display_name();
+
+    let root_resrc = resources.get(&slot_info.root_level)
+        .ok_or(DownloadError::RootMissing)?;
     let root_resrc = ResrcData::new(root_resrc, false)?;
 
     let mut revision = match root_resrc.method {
         ResrcMethod::Binary { revision, .. } => revision,
-        _ => return Err(anyhow!("rootLevel uses non-binary serialization method, is this corrupted?"))
+        _ => return Err(ResrcError::NonBinaryRoot.into())
     };
 
     let mut gameversion = revision.get_gameversion();
-    if force_lbp3 {
+    if let Some(target) = options.revision {
+        // an older head, or the same head without the root's branch revision
+        if revision.is_newer_than(&target) {
+            return Err(ResrcError::RevisionTooOld(target, revision).into());
+        }
+        revision = target;
+        gameversion = revision.get_gameversion();
+        eprintln!("WARNING: Writing {} backup with revision {revision}", gameversion.get_short_title());
+    } else if options.force_lbp3 {
         if gameversion != GameVersion::Lbp3 {
             eprintln!("WARNING: Writing LBP3 backup");
             gameversion = GameVersion::Lbp3;
@@ -134,22 +601,85 @@ async fn dl_as_backup(level_id: i64, config: Config, force_lbp3: bool) -> Result
         }
     }
 
-    let slot_id_str = hex::encode_upper(u32::to_be_bytes(level_id as u32));
+    // the root can be older than some of the plans and objects in the level, which is fine as long
+    // as the targeted game can read them, so they're checked against its latest revision
+    // (or the one picked with --revision) rather than the root's
+    let readable = match options.revision {
+        Some(target) => target,
+        None => gameversion.get_latest_revision(),
+    };
+    let newer = get_newer_resources(&resources, &readable);
+    if let Some((_, _, newest)) = newer.first() {
+        eprintln!(
+            "WARNING: {} resources are newer than the {} backup can read ({readable}):",
+            newer.len(),
+            gameversion.get_short_title(),
+        );
+        for (sha1, resrc_type, resrc_revision) in &newer {
+            eprintln!(
+                "  {} {} {resrc_revision} ({})",
+                hex::encode(sha1),
+                resrc_type.escape_ascii(),
+                resrc_revision.get_gameversion().get_short_title(),
+            );
+        }
+
+        let newest_gameversion = newest.get_gameversion();
+        if newest_gameversion == gameversion && options.revision.is_some() {
+            eprintln!("WARNING: These need a newer {} revision than the one picked with --revision", gameversion.get_short_title());
+        } else if newest_gameversion == gameversion {
+            eprintln!("WARNING: These are newer than any known {} revision, the backup might not load", gameversion.get_short_title());
+        } else if options.revision.is_none() && (options.upgrade || config.upgrade_mixed_revisions) {
+            eprintln!("WARNING: Upgrading to {} backup", newest_gameversion.get_short_title());
+            gameversion = newest_gameversion;
+            revision = gameversion.get_latest_revision();
+        } else {
+            eprintln!("WARNING: Use --upgrade or enable upgrade_mixed_revisions to write a {} backup instead", newest_gameversion.get_short_title());
+        }
+    }
+
+    let titleid = get_titleid(gameversion, options.region, options.title_id.as_deref())?;
+    let slot_id_str = hex::encode_upper(u32::to_be_bytes(slot_id));
     let bkp_name = match slot_info.is_adventure_planet {
-        false => format!("{}LEVEL{}", gameversion.get_titleid(), slot_id_str),
-        true => format!("{}ADVLBP3AAZ{}", gameversion.get_titleid(), slot_id_str),
+        false => format!("{titleid}LEVEL{slot_id_str}"),
+        true => format!("{titleid}ADVLBP3AAZ{slot_id_str}"),
     };
     let bkp_path = config.backup_directory.join(&bkp_name);
+    let detail = make_detail(&config.detail_template, &slot_info, &gameversion, provenance);
     fs::create_dir_all(&bkp_path)?;
 
     let slt = make_slotlist(&revision, &slot_info)?;
     let slt_hash = Sha1::digest(&slt).into();
     resources.insert(slt_hash, slt);
 
-    make_icon(&bkp_path, icon_sha1, &mut resources)?;
+    let icon = match custom_icon {
+        Some(img) => Some(img),
+        None => {
+            let icon_resrc = match &local_icon {
+                Some(icon) => Some(icon),
+                None => icon_sha1.and_then(|hash| resources.get(&hash)),
+            };
+            match icon_resrc.map(|icon_resrc| decode_icon(icon_resrc)) {
+                Some(Ok(icon)) => icon,
+                // a broken icon shouldn't stop the backup, the placeholder is used instead
+                Some(Err(error)) => {
+                    eprintln!("WARNING: Couldn't decode the level icon, using the placeholder: {error}");
+                    None
+                },
+                None => None,
+            }
+        },
+    };
+    make_icon(&bkp_path, icon.as_ref(), &slot_info, provenance.level_id)?;
+
+    match custom_pic1 {
+        Some(img) => write_pic1(&bkp_path, &img)?,
+        None if options.pic1 => make_pic1(&bkp_path, icon.as_ref())?,
+        None => {},
+    }
 
     make_savearchive(&revision, slt_hash, resources, &bkp_path)?;
-    let sfo = make_sfo(&slot_info, display_name, &bkp_name, &bkp_path, &gameversion)?;
+    let sfo = make_sfo(&slot_info, display_name, &detail, &bkp_name, &bkp_path, &gameversion, is_partial)?;
 
     let pfd_version = match gameversion {
         GameVersion::Lbp3 => 4,
@@ -161,16 +691,157 @@ async fn dl_as_backup(level_id: i64, config: Config, force_lbp3: bool) -> Result
     Ok(())
 }
 
+fn edit_sfo(dir: PathBuf, set: Vec<(String, String)>, remove: Vec<String>) -> Result<()> {
+    let sfo_path = dir.join("PARAM.SFO");
+    let mut sfo = Sfo::parse(&fs::read(&sfo_path)?)?;
+
+    if set.is_empty() && remove.is_empty() {
+        for (key, entry) in &sfo.entries {
+            println!("{key} ({}, max {} bytes): {}", entry.value.get_type_name(), entry.max_size, entry.value);
+        }
+        return Ok(());
+    }
+
+    // a new PARAM.PFD only protects PARAM.SFO, so don't replace one that protects save data too
+    let pfd = read_pfd(&dir)?;
+    if let Some(PfdInfo { files, .. }) = &pfd {
+        let others: Vec<_> = files.iter().filter(|file| *file != "PARAM.SFO").map(String::as_str).collect();
+        if !others.is_empty() {
+            return Err(SerializeError::PfdOtherFiles(others.join(", ")).into());
+        }
+    }
+
+    for key in &remove {
+        if sfo.remove(key).is_none() {
+            eprintln!("WARNING: {key} isn't in PARAM.SFO");
+        }
+    }
+    for (key, value) in set {
+        // existing fields keep their type and max size, new ones are strings
+        let value = match sfo.get(&key) {
+            Some(old) => old.parse_like(&value)?,
+            None => SfoValue::String(value),
+        };
+        sfo.try_set(&key, value)?;
+    }
+
+    let version = match &pfd {
+        Some(pfd) => pfd.version,
+        None => {
+            eprintln!("WARNING: PARAM.PFD is missing, writing a new one");
+            get_pfd_version(&sfo)
+        },
+    };
+
+    let sfo = sfo.serialize()?;
+    fs::write(&sfo_path, &sfo)?;
+    println!("PARAM.SFO written");
+
+    make_pfd(version, sfo, &dir)?;
+    println!("PARAM.PFD signed");
+
+    Ok(())
+}
+
+// LBP3 saves need a version 4 PARAM.PFD, the game is found from the title ID
+fn get_pfd_version(sfo: &Sfo) -> u64 {
+    let titleid = ["TITLE_ID", "SAVEDATA_DIRECTORY"].into_iter().find_map(|key| match sfo.get(key) {
+        Some(SfoValue::String(s)) => Some(s.as_str()),
+        _ => None,
+    });
+    match titleid.and_then(GameVersion::from_titleid) {
+        Some(GameVersion::Lbp3) => 4,
+        Some(_) => 3,
+        None => {
+            eprintln!("WARNING: Couldn't tell which game the save is for, using PARAM.PFD version 3");
+            3
+        },
+    }
+}
+
+async fn dl_textures(level_id: i64, config: Config, output: PathBuf, filter: TypeFilter) -> Result<()> {
+    let LevelDownload { resources, skipped, .. } = download_slot(level_id, &config, filter, None).await?;
+
+    let skipped: Vec<[u8; 20]> = skipped.into_iter()
+        .filter(|(_, resrc_type)| matches!(resrc_type, Some(ResourceType::Texture | ResourceType::GtfTexture)))
+        .map(|(sha1, _)| sha1)
+        .collect();
+
+    let dir = output.join(level_id.to_string());
+    let ExportStats { exported, surfaces, failed, skipped } = export_textures(&resources, &skipped, &dir)?;
+
+    println!("{exported} textures exported ({surfaces} surfaces), {failed} failed");
+    if skipped != 0 {
+        println!("{skipped} textures skipped since they're in the game data, see manifest.yml");
+    }
+    println!("Textures written to {}", dir.display());
+    Ok(())
+}
+
+async fn dl_stats(level_id: i64, config: Config, filter: TypeFilter) -> Result<()> {
+    let LevelDownload { resources, .. } = download_slot(level_id, &config, filter, None).await?;
+    print!("{}", ResourceStats::new(&resources));
+    Ok(())
+}
+
+fn get_exit_code(error: &anyhow::Error) -> u8 {
+    if error.is::<ConfigError>() {
+        2
+    } else if error.is::<DbError>() {
+        3
+    } else if error.is::<DownloadError>() {
+        4
+    } else if error.is::<ResrcError>() || error.is::<GtfError>() {
+        5
+    } else if error.is::<SerializeError>() {
+        6
+    } else if error.is::<GuidMapError>() || error.is::<GameDataError>() {
+        7
+    } else if error.is::<PackError>() {
+        8
+    } else if error.is::<ExportError>() || error.is::<IconError>() {
+        9
+    } else {
+        1
+    }
+}
+
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() -> ExitCode {
+    match run().await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("ERROR: {error}");
+            ExitCode::from(get_exit_code(&error))
+        },
+    }
+}
+
+async fn run() -> Result<()> {
     let config = Config::read()?;
 
     let cli = Cli::parse();
+    if let Commands::Bkp { slot, .. } = &cli.command {
+        slot.check();
+    }
 
     match cli.command {
-        Commands::Bkp { level_id, lbp3 } => {
-            let force_lbp3 = lbp3 || config.force_lbp3_backups;
-            dl_as_backup(level_id, config, force_lbp3).await?
+        Commands::Bkp { level_id, backup, slot, filter } => {
+            let options = BackupOptions::new(backup, &config);
+            dl_as_backup(level_id, config, filter.into(), options, slot).await?
+        },
+        Commands::Pack { root_level, slot, dependencies, backup } => {
+            let options = BackupOptions::new(backup, &config);
+            pack_backup(root_level, slot, dependencies, config, options)?
+        },
+        Commands::Sfo { dir, set, remove } => {
+            edit_sfo(dir, set, remove)?
+        },
+        Commands::Textures { level_id, output, filter } => {
+            dl_textures(level_id, config, output, filter.into()).await?
+        },
+        Commands::Stats { level_id, filter } => {
+            dl_stats(level_id, config, filter.into()).await?
         },
     }
 
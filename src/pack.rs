@@ -0,0 +1,186 @@
+use std::{collections::{BTreeMap, BTreeSet}, fs::{self, File}, path::Path};
+
+use serde::Deserialize;
+use sha1::{Digest, Sha1};
+use thiserror::Error;
+
+use crate::{db::{GameVersion, LevelType, SlotInfo}, labels::get_label_key_id, resource_parse::{ResrcData, ResrcDescriptor, ResrcMethod}};
+
+#[derive(Error, Debug)]
+pub enum PackError {
+    #[error("Couldn't read {0}: {1}")]
+    Open(String, std::io::Error),
+    #[error("invalid slot file: {0}")]
+    InvalidSlotFile(String),
+    #[error("unknown author label: {0}")]
+    UnknownLabel(String),
+    #[error("icon {0} from the slot file isn't in the dependency directory")]
+    IconMissing(String),
+    #[error("yaml error: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+    #[error("io error: {0}")]
+    IOError(#[from] std::io::Error),
+}
+
+type Result<T> = std::result::Result<T, PackError>;
+
+/// Slot fields for a level that isn't in the database, read from YAML or JSON
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SlotFile {
+    name: String,
+    #[serde(default)]
+    description: String,
+    creator: String,
+    // SHA1 or GUID, the SHA1 one has to be in the dependency directory
+    icon: Option<String>,
+    #[serde(default)]
+    labels: Vec<String>,
+    level_type: Option<String>,
+    min_players: Option<u8>,
+    max_players: Option<u8>,
+    #[serde(default)]
+    initially_locked: bool,
+    #[serde(default)]
+    is_sub_level: bool,
+    #[serde(default)]
+    shareable: bool,
+    background_guid: Option<u32>,
+}
+
+/// Reads a slot file, the game is the one the root level was saved by
+pub fn read_slot_file(path: &Path, root_level: [u8; 20], game: GameVersion) -> Result<SlotInfo> {
+    // yaml is a superset of json, so this reads both
+    let file = File::open(path).map_err(|e| PackError::Open(path.display().to_string(), e))?;
+    let slot_file: SlotFile = serde_yaml::from_reader(file)?;
+
+    let icon = match slot_file.icon {
+        Some(icon) => icon.parse().map_err(PackError::InvalidSlotFile)?,
+        None => ResrcDescriptor::Guid(0),
+    };
+
+    let author_labels = slot_file.labels.iter()
+        .map(|label| get_label_key_id(label).ok_or_else(|| PackError::UnknownLabel(label.clone())))
+        .collect::<Result<_>>()?;
+
+    if let (Some(min_players), Some(max_players)) = (slot_file.min_players, slot_file.max_players) && min_players > max_players {
+        return Err(PackError::InvalidSlotFile(format!("min_players {min_players} is more than max_players {max_players}")));
+    }
+
+    let leveltype = match slot_file.level_type {
+        Some(level_type) => level_type.parse().map_err(PackError::InvalidSlotFile)?,
+        None => LevelType::Cooperative,
+    };
+
+    Ok(SlotInfo {
+        name: slot_file.name,
+        description: slot_file.description,
+        np_handle: slot_file.creator,
+        root_level,
+        icon,
+        game,
+        initially_locked: slot_file.initially_locked,
+        is_sub_level: slot_file.is_sub_level,
+        background_guid: slot_file.background_guid,
+        shareable: slot_file.shareable,
+        author_labels,
+        leveltype,
+        min_players: slot_file.min_players,
+        max_players: slot_file.max_players,
+        is_adventure_planet: false,
+    })
+}
+
+fn read_dir_recursive(dir: &Path, resources: &mut BTreeMap<[u8; 20], Vec<u8>>) -> Result<()> {
+    let entries = fs::read_dir(dir).map_err(|e| PackError::Open(dir.display().to_string(), e))?;
+    for entry in entries {
+        let path = entry?.path();
+        if path.is_dir() {
+            read_dir_recursive(&path, resources)?;
+            continue;
+        }
+        let data = fs::read(&path).map_err(|e| PackError::Open(path.display().to_string(), e))?;
+        resources.insert(Sha1::digest(&data).into(), data);
+    }
+    Ok(())
+}
+
+pub struct LocalLevel {
+    pub root_level: [u8; 20],
+    pub resources: BTreeMap<[u8; 20], Vec<u8>>,
+}
+
+/// Reads the root level and every file in the dependency directory, keyed by their SHA1
+pub fn read_resources(root_path: &Path, dependency_dir: Option<&Path>) -> Result<LocalLevel> {
+    let mut resources = BTreeMap::new();
+    if let Some(dir) = dependency_dir {
+        read_dir_recursive(dir, &mut resources)?;
+    }
+
+    let root = fs::read(root_path).map_err(|e| PackError::Open(root_path.display().to_string(), e))?;
+    let root_sha1 = Sha1::digest(&root).into();
+    resources.insert(root_sha1, root);
+
+    Ok(LocalLevel {
+        root_level: root_sha1,
+        resources,
+    })
+}
+
+/// Drops every resource that isn't reachable from the roots (the root level and its icon),
+/// so unrelated files in the dependency directory don't end up in the backup.
+/// Returns the SHA1 dependencies that aren't in the resources, GUID dependencies are left to the game
+pub fn retain_reachable(roots: &[[u8; 20]], resources: &mut BTreeMap<[u8; 20], Vec<u8>>) -> BTreeSet<[u8; 20]> {
+    let mut missing = BTreeSet::new();
+    let mut visited = BTreeSet::new();
+    let mut queue = roots.to_vec();
+
+    while let Some(sha1) = queue.pop() {
+        if !visited.insert(sha1) {
+            continue;
+        }
+        let Some(resource) = resources.get(&sha1) else {
+            missing.insert(sha1);
+            continue;
+        };
+        if let Ok(ResrcData { method: ResrcMethod::Binary { dependencies, .. }, .. }) = ResrcData::new(resource, false) {
+            for dependency in dependencies {
+                if let ResrcDescriptor::Sha1(dep_sha1) = dependency.desc {
+                    queue.push(dep_sha1);
+                }
+            }
+        }
+    }
+
+    resources.retain(|sha1, _| visited.contains(sha1));
+    missing
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a plan depending on the SHA1 of "mesh" and a GUID
+    const PLAN_B: &[u8] = include_bytes!("../tests/fixtures/plan_b.bin");
+
+    #[test]
+    fn reachable_resources() {
+        let sha1 = |data: &[u8]| -> [u8; 20] { Sha1::digest(data).into() };
+        let plan = sha1(PLAN_B);
+
+        let mut resources = BTreeMap::from([
+            (plan, PLAN_B.to_vec()),
+            (sha1(b"mesh"), b"mesh".to_vec()),
+            (sha1(b"unrelated"), b"unrelated".to_vec()),
+            (sha1(b"icon"), b"icon".to_vec()),
+        ]);
+        let missing = retain_reachable(&[plan, sha1(b"icon")], &mut resources);
+        assert!(missing.is_empty());
+        assert_eq!(resources.keys().copied().collect::<BTreeSet<_>>(), BTreeSet::from([plan, sha1(b"mesh"), sha1(b"icon")]));
+
+        resources.remove(&sha1(b"mesh"));
+        let missing = retain_reachable(&[plan], &mut resources);
+        assert_eq!(missing, BTreeSet::from([sha1(b"mesh")]));
+        assert_eq!(resources.len(), 1);
+    }
+}
@@ -1,9 +1,11 @@
-use std::{collections::{BTreeSet, BTreeMap}, io::{stdout, Write}, result};
+use std::{collections::{BTreeSet, BTreeMap}, io::{stdout, Write}};
 use std::sync::{Arc, Mutex};
 use std::sync::atomic::{AtomicUsize, Ordering};
 
-use crate::resource_parse::{ResrcDependency, ResrcDescriptor, ResrcData, ResrcMethod};
+use crate::resource_parse::{ResrcDependency, ResrcDescriptor, ResrcData, ResrcError, ResrcMethod};
+use crate::resource_type::ResourceType;
 use crate::config::DownloadServer;
+use crate::game_data::GameData;
 use crate::USER_AGENT;
 
 use reqwest::{Client, ClientBuilder, StatusCode};
@@ -11,14 +13,25 @@ use sha1::{Digest, Sha1};
 use futures_util::future::BoxFuture;
 use futures_util::FutureExt;
 use tokio::sync::{AcquireError, Semaphore};
-use tokio::task::JoinSet;
-use anyhow::{anyhow, Result};
+use tokio::task::{JoinError, JoinSet};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
-enum DownloadError {
+pub enum DownloadError {
+    #[error("rootLevel is missing from the archive, rip")]
+    RootMissing,
+    #[error("Couldn't acquire mutex in {0}")]
+    MutexPoisoned(&'static str),
+    #[error("couldn't unwrap downloader cache")]
+    CacheUnwrap,
+    #[error("downloaded resource doesn't match its hash: {0}")]
+    HashMismatch(String),
     #[error("status code error: {0}")]
     StatusCode(StatusCode),
+    #[error("resource error: {0}")]
+    Resrc(#[from] ResrcError),
+    #[error("task join error: {0}")]
+    Join(#[from] JoinError),
     #[error("io error: {0}")]
     IOError(#[from] std::io::Error),
     #[error("semaphore acquire error: {0}")]
@@ -27,6 +40,30 @@ enum DownloadError {
     Reqwest(#[from] reqwest::Error),
 }
 
+type Result<T> = std::result::Result<T, DownloadError>;
+
+/// Which dependency types get downloaded, based on the types in dependency tables.
+/// An empty include list allows every type that isn't excluded.
+#[derive(Debug, Default, Clone)]
+pub struct TypeFilter {
+    pub include: Vec<ResourceType>,
+    pub exclude: Vec<ResourceType>,
+}
+
+impl TypeFilter {
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    // compared by id, so textures and gtf textures are the same thing here
+    fn allows(&self, resrc_type: Option<ResourceType>) -> bool {
+        let matches = |types: &[ResourceType]| {
+            resrc_type.is_some_and(|t| types.iter().any(|other| other.get_id() == t.get_id()))
+        };
+        (self.include.is_empty() || matches(&self.include)) && !matches(&self.exclude)
+    }
+}
+
 #[derive(Clone)]
 struct Downloader {
     client: Client,
@@ -35,12 +72,19 @@ struct Downloader {
     cache: Arc<Mutex<BTreeMap<[u8; 20], Vec<u8>>>>,
     semaphore: Arc<Semaphore>,
 
+    game_data: Arc<GameData>,
+    filter: Arc<TypeFilter>,
+    filtered: Arc<Mutex<BTreeSet<ResrcDescriptor>>>,
+    skipped: Arc<Mutex<BTreeMap<[u8; 20], Option<ResourceType>>>>,
+    resolved_guids: Arc<Mutex<BTreeSet<u32>>>,
+    unresolved_guids: Arc<Mutex<BTreeSet<u32>>>,
+
     successful: Arc<AtomicUsize>,
     failed: Arc<AtomicUsize>,
 }
 
 impl<'a> Downloader {
-    fn new(download_server: DownloadServer, max_parallel: usize) -> Result<Self> {
+    fn new(download_server: DownloadServer, max_parallel: usize, game_data: Arc<GameData>, filter: TypeFilter) -> Result<Self> {
         let client = ClientBuilder::new()
             .user_agent(USER_AGENT)
             .build()?;
@@ -51,29 +95,67 @@ impl<'a> Downloader {
             cache: Arc::new(Mutex::new(BTreeMap::new())),
             semaphore: Arc::new(Semaphore::new(max_parallel)),
 
+            game_data,
+            filter: Arc::new(filter),
+            filtered: Arc::new(Mutex::new(BTreeSet::new())),
+            skipped: Arc::new(Mutex::new(BTreeMap::new())),
+            resolved_guids: Arc::new(Mutex::new(BTreeSet::new())),
+            unresolved_guids: Arc::new(Mutex::new(BTreeSet::new())),
+
             successful: Arc::new(AtomicUsize::new(0)),
             failed: Arc::new(AtomicUsize::new(0)),
         })
     }
 
     fn is_downloaded(&self, hash: [u8; 20]) -> Result<bool> {
-        let lock = self.downloaded.lock().map_err(|_| anyhow!("Couldn't acquire mutex in is_downloaded"))?;
+        let lock = self.downloaded.lock().map_err(|_| DownloadError::MutexPoisoned("is_downloaded"))?;
         Ok((*lock).contains(&hash))
     }
 
     fn set_downloaded(&self, hash: [u8; 20]) -> Result<()> {
-        let mut lock = self.downloaded.lock().map_err(|_| anyhow!("Couldn't acquire mutex in set_downloaded"))?;
+        let mut lock = self.downloaded.lock().map_err(|_| DownloadError::MutexPoisoned("set_downloaded"))?;
         (*lock).insert(hash);
         Ok(())
     }
 
     fn add_to_cache(&self, hash: [u8; 20], data: Vec<u8>) -> Result<()> {
-        let mut lock = self.cache.lock().map_err(|_| anyhow!("Couldn't acquire mutex in add_to_cache"))?;
+        let mut lock = self.cache.lock().map_err(|_| DownloadError::MutexPoisoned("add_to_cache"))?;
         (*lock).insert(hash, data);
         Ok(())
     }
 
-    async fn download_resource(&self, sha1: &[u8; 20]) -> result::Result<Vec<u8>, DownloadError> {
+    fn resolve_guid(&self, guid: u32) -> Result<Option<[u8; 20]>> {
+        let sha1 = self.game_data.guid_map.get(guid).map(|entry| entry.sha1);
+        let guids = match sha1 {
+            Some(_) => &self.resolved_guids,
+            None => &self.unresolved_guids,
+        };
+        let mut lock = guids.lock().map_err(|_| DownloadError::MutexPoisoned("resolve_guid"))?;
+        (*lock).insert(guid);
+        Ok(sha1)
+    }
+
+    /// Returns true if the dependency's type is filtered out, so it doesn't need to be downloaded
+    fn skip_if_filtered(&self, dependency: &ResrcDependency) -> Result<bool> {
+        if self.filter.allows(dependency.get_type()) {
+            return Ok(false);
+        }
+        let mut lock = self.filtered.lock().map_err(|_| DownloadError::MutexPoisoned("skip_if_filtered"))?;
+        (*lock).insert(dependency.desc);
+        Ok(true)
+    }
+
+    /// Returns true if the resource is in the local game data, so it doesn't need to be downloaded
+    fn skip_if_local(&self, sha1: [u8; 20], resrc_type: Option<ResourceType>) -> Result<bool> {
+        if !self.game_data.contains(&sha1) {
+            return Ok(false);
+        }
+        let mut lock = self.skipped.lock().map_err(|_| DownloadError::MutexPoisoned("skip_if_local"))?;
+        (*lock).insert(sha1, resrc_type);
+        Ok(true)
+    }
+
+    async fn download_resource(&self, sha1: &[u8; 20]) -> Result<Vec<u8>> {
         let url = self.download_server.get_url(sha1);
         let mut resp = {
             let _permit = self.semaphore.acquire().await?;
@@ -93,7 +175,9 @@ impl<'a> Downloader {
             resource.write_all(&chunk)?;
         }
 
-        assert_eq!(Sha1::digest(&resource).as_slice(), sha1);
+        if Sha1::digest(&resource).as_slice() != sha1 {
+            return Err(DownloadError::HashMismatch(hex::encode(sha1)));
+        }
 
         Ok(resource)
     }
@@ -120,13 +204,13 @@ impl<'a> Downloader {
                 resource
             },
             Err(error) => {
-                if let DownloadError::StatusCode(_) = error {
+                if let DownloadError::StatusCode(_) | DownloadError::HashMismatch(_) = error {
                     print!("!");
                     stdout().flush()?;
                     self.failed.fetch_add(1, Ordering::SeqCst);
                     return Ok(());
                 }
-                return Err(anyhow!(error));
+                return Err(error);
             }
         };
 
@@ -137,13 +221,24 @@ impl<'a> Downloader {
         let mut tasks = JoinSet::new();
 
         if let ResrcMethod::Binary { dependencies, .. } = metadata.method {
-            for ResrcDependency { desc, .. } in dependencies {
-                if let ResrcDescriptor::Sha1(sha1) = desc {
-                    let downloader = self.clone();
-                    tasks.spawn(async move {
-                        downloader.download_boxed(&sha1).await
-                    });
+            for dependency in dependencies {
+                if self.skip_if_filtered(&dependency)? {
+                    continue;
                 }
+                let sha1 = match dependency.desc {
+                    ResrcDescriptor::Sha1(sha1) => sha1,
+                    ResrcDescriptor::Guid(guid) => match self.resolve_guid(guid)? {
+                        Some(sha1) => sha1,
+                        None => continue,
+                    },
+                };
+                if self.skip_if_local(sha1, dependency.get_type())? {
+                    continue;
+                }
+                let downloader = self.clone();
+                tasks.spawn(async move {
+                    downloader.download_boxed(&sha1).await
+                });
             }
         }
 
@@ -158,6 +253,22 @@ impl<'a> Downloader {
     fn get_stats(&self) -> (usize, usize) {
         (self.successful.load(Ordering::SeqCst), self.failed.load(Ordering::SeqCst))
     }
+
+    fn take_skipped(&self) -> Result<BTreeMap<[u8; 20], Option<ResourceType>>> {
+        let mut lock = self.skipped.lock().map_err(|_| DownloadError::MutexPoisoned("take_skipped"))?;
+        Ok(std::mem::take(&mut *lock))
+    }
+
+    fn take_filtered(&self) -> Result<BTreeSet<ResrcDescriptor>> {
+        let mut lock = self.filtered.lock().map_err(|_| DownloadError::MutexPoisoned("take_filtered"))?;
+        Ok(std::mem::take(&mut *lock))
+    }
+
+    fn take_guids(&self) -> Result<(BTreeSet<u32>, BTreeSet<u32>)> {
+        let mut resolved = self.resolved_guids.lock().map_err(|_| DownloadError::MutexPoisoned("take_guids"))?;
+        let mut unresolved = self.unresolved_guids.lock().map_err(|_| DownloadError::MutexPoisoned("take_guids"))?;
+        Ok((std::mem::take(&mut *resolved), std::mem::take(&mut *unresolved)))
+    }
 }
 
 pub struct DownloadResult {
@@ -167,6 +278,14 @@ pub struct DownloadResult {
     pub resources: BTreeMap<[u8; 20], Vec<u8>>,
     pub success_count: usize,
     pub error_count: usize,
+    // GUID dependencies that were (or weren't) found in the GUID map
+    pub resolved_guids: BTreeSet<u32>,
+    pub unresolved_guids: BTreeSet<u32>,
+    // dependencies that weren't downloaded since they're already in the game data, with their types
+    pub skipped: BTreeMap<[u8; 20], Option<ResourceType>>,
+    pub skipped_size: u64,
+    // dependencies that weren't downloaded since their type is filtered out
+    pub filtered: BTreeSet<ResrcDescriptor>,
 }
 
 pub async fn download_level(
@@ -174,8 +293,10 @@ pub async fn download_level(
     icon_sha1: Option<[u8; 20]>,
     download_server: DownloadServer,
     max_parallel: usize,
+    game_data: Arc<GameData>,
+    filter: TypeFilter,
 ) -> Result<DownloadResult> {
-    let downloader = Downloader::new(download_server, max_parallel)?;
+    let downloader = Downloader::new(download_server, max_parallel, game_data, filter)?;
 
     let mut tasks = JoinSet::new();
 
@@ -198,13 +319,26 @@ pub async fn download_level(
     }
 
     let (success_count, error_count) = downloader.get_stats();
+    let (resolved_guids, unresolved_guids) = downloader.take_guids()?;
+    let skipped = downloader.take_skipped()?;
+    let filtered = downloader.take_filtered()?;
+    let skipped_size = skipped.keys()
+        .filter_map(|sha1| downloader.game_data.get_size(sha1))
+        .map(u64::from)
+        .sum();
     let resources = Arc::try_unwrap(downloader.cache)
-        .map_err(|_| anyhow!("couldn't unwrap downloader cache"))?
-        .into_inner()?;
+        .map_err(|_| DownloadError::CacheUnwrap)?
+        .into_inner()
+        .map_err(|_| DownloadError::MutexPoisoned("download_level"))?;
 
     Ok(DownloadResult {
         resources,
         success_count,
         error_count,
+        resolved_guids,
+        unresolved_guids,
+        skipped,
+        skipped_size,
+        filtered,
     })
 }
\ No newline at end of file
@@ -1,11 +1,42 @@
-use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+use std::{fmt, io::{Cursor, Read, Seek, SeekFrom, Write}, str::FromStr};
 
-use crate::{db::GameVersion, gtf_texture::{CellGcmEnumForGtf, CellGcmTexture}};
+use crate::{db::{GameVersion, KNOWN_REVISIONS}, resource_type::ResourceType, xxtea, gtf_texture::{CellGcmEnumForGtf, CellGcmTexture, GtfError, CELL_GCM_TEXTURE_LN}};
 
 use byteorder::{BigEndian, ReadBytesExt};
-use miniz_oxide::inflate::core::{decompress, DecompressorOxide};
+use miniz_oxide::deflate::compress_to_vec_zlib;
+use miniz_oxide::inflate::{core::{decompress, DecompressorOxide}, TINFLStatus};
 use miniz_oxide::inflate::core::inflate_flags::{TINFL_FLAG_PARSE_ZLIB_HEADER, TINFL_FLAG_USING_NON_WRAPPING_OUTPUT_BUF};
-use anyhow::{anyhow, Result};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ResrcError {
+    #[error("invalid type in dependency table: {0}")]
+    InvalidDependencyType(u8),
+    #[error("{} resource isn't a texture", String::from_utf8_lossy(.0))]
+    NotTexture([u8; 3]),
+    #[error("compressed chunks are truncated")]
+    Truncated,
+    #[error("encrypted resource body is truncated")]
+    EncryptedTruncated,
+    #[error("decompression error: {0:?}")]
+    Decompress(TINFLStatus),
+    #[error("rootLevel uses non-binary serialization method, is this corrupted?")]
+    NonBinaryRoot,
+    #[error("{} resource isn't a binary resource", String::from_utf8_lossy(.0))]
+    NotBinary([u8; 3]),
+    #[error("{} resource bodies aren't supported", String::from_utf8_lossy(.0))]
+    UnsupportedBody([u8; 3]),
+    #[error("target revision {0} can't read the root level's revision {1}")]
+    RevisionTooOld(ResrcRevision, ResrcRevision),
+    #[error("gtf error: {0}")]
+    Gtf(#[from] GtfError),
+    #[error("io error: {0}")]
+    IOError(#[from] std::io::Error),
+}
+
+type Result<T> = std::result::Result<T, ResrcError>;
+
+const COMPRESSION_CHUNK_SIZE: usize = 0x8000;
 
 #[derive(Debug, PartialEq, Eq, Hash)]
 pub struct ResrcData {
@@ -13,7 +44,7 @@ pub struct ResrcData {
     pub method: ResrcMethod,
 }
 
-#[derive(Debug, PartialEq, Eq, Hash)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
 pub struct ResrcRevision {
     pub head: u32,
     pub branch_id: u16,
@@ -42,6 +73,63 @@ impl ResrcRevision {
             GameVersion::Lbp2
         }
     }
+    /// True if a game reading `other` resources can't be expected to read this one,
+    /// branches only matter when the heads are the same
+    pub fn is_newer_than(&self, other: &Self) -> bool {
+        if self.head != other.head {
+            return self.head > other.head;
+        }
+        match self.branch_id {
+            0 => false,
+            branch_id if branch_id == other.branch_id => self.branch_revision > other.branch_revision,
+            // a branch that the other revision isn't on
+            _ => true,
+        }
+    }
+}
+
+/// Same format as [`ResrcRevision::from_str`], the branch is left out if there's none
+impl fmt::Display for ResrcRevision {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:#x}", self.head)?;
+        if self.branch_id != 0 || self.branch_revision != 0 {
+            write!(f, ":{:#x}:{:#x}", self.branch_id, self.branch_revision)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses a known revision name, or a hex revision in the form head[:branch_id:branch_revision]
+impl FromStr for ResrcRevision {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        if let Some(known) = KNOWN_REVISIONS.iter().find(|known| known.name.eq_ignore_ascii_case(s)) {
+            return Ok(known.revision);
+        }
+
+        let invalid = || {
+            let names: Vec<_> = KNOWN_REVISIONS.iter().map(|known| known.name).collect();
+            format!("expected one of {} or a hex revision like 0x3f8 or 0x272:0x4c44:0x17", names.join(", "))
+        };
+        let hex = |part: &str| {
+            let digits = part.strip_prefix("0x").or_else(|| part.strip_prefix("0X")).unwrap_or(part);
+            u32::from_str_radix(digits, 16).map_err(|_| invalid())
+        };
+
+        let parts: Vec<&str> = s.split(':').collect();
+        let (head, branch_id, branch_revision) = match parts[..] {
+            [head] => (hex(head)?, 0, 0),
+            [head, branch_id, branch_revision] => (hex(head)?, hex(branch_id)?, hex(branch_revision)?),
+            _ => return Err(invalid()),
+        };
+
+        Ok(Self {
+            head,
+            branch_id: branch_id.try_into().map_err(|_| invalid())?,
+            branch_revision: branch_revision.try_into().map_err(|_| invalid())?,
+        })
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, Hash)]
@@ -64,15 +152,112 @@ pub struct ResrcDependency {
     resrc_type: u32,
 }
 
-#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
 pub enum ResrcDescriptor {
     Sha1([u8; 20]),
     Guid(u32),
 }
 
-impl ResrcDependency {
-    pub fn parse_table(res: &mut Cursor<&[u8]>) -> Result<Vec<Self>> {
+/// Parses a hex SHA1, or a GUID in the form g12345
+impl FromStr for ResrcDescriptor {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let invalid = || format!("{s} isn't a SHA1 or a GUID like g12345");
+        if let Some(guid) = s.strip_prefix('g') {
+            return guid.parse().map(Self::Guid).map_err(|_| invalid());
+        }
+        let mut sha1 = [0u8; 20];
+        hex::decode_to_slice(s, &mut sha1).map_err(|_| invalid())?;
+        Ok(Self::Sha1(sha1))
+    }
+}
+
+/// Decompressed body of a binary resource, everything between the header and the dependency table
+#[derive(Debug, PartialEq, Eq, Hash)]
+pub struct ResrcBody {
+    pub resrc_type: [u8; 3],
+    pub is_encrypted: bool,
+    pub revision: ResrcRevision,
+    // flags for how integers, vectors and matrices are packed in the body, not zlib compression
+    pub compression_flags: u8,
+    pub data: Vec<u8>,
+}
+
+// everything in a binary resource header, the stream is left at the start of the body
+struct BinaryHeader {
+    revision: ResrcRevision,
+    dependencies: Vec<ResrcDependency>,
+    dep_table_offset: Option<u32>,
+    compression_flags: u8,
+    is_compressed: bool,
+}
+
+// header layout based on SerializedResource from cwlib
+// https://github.com/ennuo/toolkit/tree/main/lib/cwlib
+fn read_binary_header(res: &mut Cursor<&[u8]>, resrc_type: &[u8; 3]) -> Result<BinaryHeader> {
+    let mut header = BinaryHeader {
+        revision: ResrcRevision {
+            head: res.read_u32::<BigEndian>()?,
+            branch_id: 0,
+            branch_revision: 0,
+        },
+        dependencies: vec![],
+        dep_table_offset: None,
+        compression_flags: 0,
+        // bodies are always compressed before the flag was added
+        is_compressed: true,
+    };
+    let rev = &mut header.revision;
+
+    if rev.head >= 0x109 {
         let table_offset = res.read_u32::<BigEndian>()?;
+        header.dependencies = ResrcDependency::parse_table(res, table_offset)?;
+        header.dep_table_offset = Some(table_offset);
+    }
+
+    if resrc_type != b"SMH" && rev.head >= 0x271 {
+        rev.branch_id = res.read_u16::<BigEndian>()?;
+        rev.branch_revision = res.read_u16::<BigEndian>()?;
+    }
+
+    if resrc_type != b"SMH" && rev.head >= 0x189 {
+        if rev.head >= 0x297 || (rev.head == 0x272 && rev.branch_id == 0x4c44 && rev.branch_revision >= 0x2) {
+            header.compression_flags = res.read_u8()?;
+        }
+        header.is_compressed = res.read_u8()? != 0;
+    }
+
+    Ok(header)
+}
+
+// encrypted bodies come right after the header, as a size and then the
+// xxtea encrypted data, padded to a multiple of 4 bytes.
+// the padding is assumed to be at the end, which hasn't been checked against
+// a real encrypted resource or cwlib yet
+fn decrypt_body(res: &mut Cursor<&[u8]>) -> Result<Vec<u8>> {
+    let size = res.read_u32::<BigEndian>()? as usize;
+    let padded_size = size.next_multiple_of(4);
+
+    let remaining = (res.get_ref().len() as u64).saturating_sub(res.position());
+    if padded_size as u64 > remaining {
+        return Err(ResrcError::EncryptedTruncated);
+    }
+
+    let mut data = vec![0u8; padded_size];
+    res.read_exact(&mut data)?;
+    xxtea::decrypt(&xxtea::TEA_KEY, &mut data);
+    data.truncate(size);
+
+    Ok(data)
+}
+
+impl ResrcDependency {
+    pub fn get_type(&self) -> Option<ResourceType> {
+        ResourceType::from_id(self.resrc_type)
+    }
+
+    pub fn parse_table(res: &mut Cursor<&[u8]>, table_offset: u32) -> Result<Vec<Self>> {
         let orig_offset = res.position();
 
         res.seek(SeekFrom::Start(table_offset as u64))?;
@@ -90,7 +275,7 @@ impl ResrcDependency {
                     ResrcDescriptor::Sha1(sha1)
                 },
                 2 => ResrcDescriptor::Guid(res.read_u32::<BigEndian>()?),
-                _ => return Err(anyhow!("invalid type in dependency table, what the fuck???")),
+                n => return Err(ResrcError::InvalidDependencyType(n)),
             };
 
             let resrc_type = res.read_u32::<BigEndian>()?;
@@ -107,7 +292,94 @@ impl ResrcDependency {
     }
 }
 
+/// Reads a zlib chunk table and inflates every chunk that follows it.
+pub fn decompress_chunks(res: &mut Cursor<&[u8]>) -> Result<Vec<u8>> {
+    res.seek(SeekFrom::Current(2))?; // unused i16, always 0x0001
+    let num_chunks = res.read_u16::<BigEndian>()?;
+
+    let mut chunk_infos = Vec::with_capacity(num_chunks as usize);
+    let mut total_compressed_size = 0;
+    let mut total_decompressed_size = 0;
+
+    #[derive(Debug)]
+    struct ChunkInfo {
+        compressed_size: u16,
+        decompressed_size: u16,
+    }
+
+    for _ in 0..num_chunks {
+        let info = ChunkInfo {
+            compressed_size: res.read_u16::<BigEndian>()?,
+            decompressed_size: res.read_u16::<BigEndian>()?,
+        };
+        total_compressed_size += info.compressed_size as u64;
+        total_decompressed_size += info.decompressed_size as usize;
+        chunk_infos.push(info);
+    }
+
+    // don't allocate the output buffer if the chunks can't possibly be there
+    let remaining = (res.get_ref().len() as u64).saturating_sub(res.position());
+    if total_compressed_size > remaining {
+        return Err(ResrcError::Truncated);
+    }
+
+    let mut final_data = vec![0u8; total_decompressed_size];
+
+    let mut decompressor = DecompressorOxide::new();
+
+    let mut final_pos = 0;
+    for info in chunk_infos {
+        let mut deflated_data = vec![0u8; info.compressed_size as usize];
+        res.read_exact(&mut deflated_data)?;
+
+        if info.compressed_size == info.decompressed_size {
+            (&mut final_data[final_pos..]).write_all(&deflated_data)?;
+        } else {
+            let flags = TINFL_FLAG_PARSE_ZLIB_HEADER | TINFL_FLAG_USING_NON_WRAPPING_OUTPUT_BUF;
+            let (status, ..) = decompress(&mut decompressor, &deflated_data, &mut final_data, final_pos, flags);
+            if (status as i8) < 0 {
+                return Err(ResrcError::Decompress(status));
+            }
+            decompressor.init();
+        }
+
+        final_pos += info.decompressed_size as usize;
+    }
+
+    Ok(final_data)
+}
+
+/// Splits data into zlib compressed chunks with a chunk table, the reverse of [`decompress_chunks`]
+pub fn compress_chunks(data: &[u8]) -> Vec<u8> {
+    let chunks: Vec<&[u8]> = data.chunks(COMPRESSION_CHUNK_SIZE).collect();
+
+    let mut table = Vec::with_capacity(4 + chunks.len() * 4);
+    let mut compressed = Vec::new();
+
+    table.extend_from_slice(&1u16.to_be_bytes()); // unused i16, always 0x0001
+    table.extend_from_slice(&(chunks.len() as u16).to_be_bytes());
+
+    for chunk in chunks {
+        let deflated = compress_to_vec_zlib(chunk, 6);
+        // chunks that don't get smaller are stored as-is
+        let stored = match deflated.len() < chunk.len() {
+            true => deflated.as_slice(),
+            false => chunk,
+        };
+        table.extend_from_slice(&(stored.len() as u16).to_be_bytes());
+        table.extend_from_slice(&(chunk.len() as u16).to_be_bytes());
+        compressed.extend_from_slice(stored);
+    }
+
+    table.extend_from_slice(&compressed);
+    table
+}
+
 impl ResrcData {
+    pub fn get_type(&self) -> Option<ResourceType> {
+        ResourceType::from_magic(&self.resrc_type)
+    }
+
     pub fn new(res: &[u8], parse_texture: bool) -> Result<Self> {
         let mut res = Cursor::new(res);
 
@@ -118,25 +390,11 @@ impl ResrcData {
 
         let method = match method {
             b'b' | b'e' => {
-                let mut rev = ResrcRevision {
-                    head: res.read_u32::<BigEndian>()?,
-                    branch_id: 0,
-                    branch_revision: 0,
-                };
-                let dependencies = match rev.head >= 0x109 {
-                    true => ResrcDependency::parse_table(&mut res)?,
-                    false => vec![],
-                };
-
-                if resrc_type != *b"SMH" && rev.head >= 0x271 {
-                    rev.branch_id = res.read_u16::<BigEndian>()?;
-                    rev.branch_revision = res.read_u16::<BigEndian>()?;
-                }
-
+                let header = read_binary_header(&mut res, &resrc_type)?;
                 ResrcMethod::Binary {
                     is_encrypted: method == b'e',
-                    revision: rev,
-                    dependencies,
+                    revision: header.revision,
+                    dependencies: header.dependencies,
                 }
             },
             b' ' => {
@@ -145,13 +403,17 @@ impl ResrcData {
                 if !parse_texture {
                     ResrcMethod::Null
                 } else {
-                    assert!([*b"TEX", *b"GTF"].contains(&resrc_type));
+                    if ![*b"TEX", *b"GTF"].contains(&resrc_type) {
+                        return Err(ResrcError::NotTexture(resrc_type));
+                    }
 
                     let mut gcm = None;
 
                     if resrc_type != *b"TEX" {
+                        let format = res.read_u8()?;
                         gcm = Some(CellGcmTexture {
-                            format: CellGcmEnumForGtf::from_u8(res.read_u8()?)?,
+                            format: CellGcmEnumForGtf::from_u8(format)?,
+                            linear: format & CELL_GCM_TEXTURE_LN != 0,
                             mipmap: res.read_u8()?,
                             dimension: res.read_u8()?,
                             cubemap: res.read_u8()?,
@@ -168,56 +430,156 @@ impl ResrcData {
                         });
                     }
 
-                    res.seek(SeekFrom::Current(2))?; // unused i16, always 0x0001
-                    let num_chunks = res.read_u16::<BigEndian>()?;
+                    let data = decompress_chunks(&mut res)?;
 
-                    let mut chunk_infos = Vec::with_capacity(num_chunks as usize);
-                    let mut total_decompressed_size = 0;
+                    ResrcMethod::Texture { data, gcm_info: gcm }
+                }
+            },
+            _ => { ResrcMethod::Null },
+        };
 
-                    #[derive(Debug)]
-                    struct ChunkInfo {
-                        compressed_size: u16,
-                        decompressed_size: u16,
-                    }
+        Ok(Self {
+            resrc_type,
+            method,
+        })
+    }
+}
 
-                    for _ in 0..num_chunks {
-                        let info = ChunkInfo {
-                            compressed_size: res.read_u16::<BigEndian>()?,
-                            decompressed_size: res.read_u16::<BigEndian>()?,
-                        };
-                        total_decompressed_size += info.decompressed_size as usize;
-                        chunk_infos.push(info);
-                    }
+impl ResrcBody {
+    pub fn get_type(&self) -> Option<ResourceType> {
+        ResourceType::from_magic(&self.resrc_type)
+    }
 
-                    let mut final_data = vec![0u8; total_decompressed_size];
+    /// Reads the header of a binary resource and decompresses its body
+    pub fn new(res: &[u8]) -> Result<Self> {
+        let mut res = Cursor::new(res);
 
-                    let mut decompressor = DecompressorOxide::new();
+        let mut resrc_type = [0u8; 3];
+        res.read_exact(&mut resrc_type)?;
 
-                    let mut final_pos = 0;
-                    for info in chunk_infos {
-                        let mut deflated_data = vec![0u8; info.compressed_size as usize];
-                        res.read_exact(&mut deflated_data[..info.compressed_size as usize])?;
+        let is_encrypted = match res.read_u8()? {
+            b'b' => false,
+            b'e' => true,
+            _ => return Err(ResrcError::NotBinary(resrc_type)),
+        };
+        // static meshes have their own header with mesh info, which isn't handled here
+        if resrc_type == *b"SMH" {
+            return Err(ResrcError::UnsupportedBody(resrc_type));
+        }
 
-                        if info.compressed_size == info.decompressed_size {
-                            (&mut final_data[final_pos..]).write_all(&deflated_data)?;
-                        } else {
-                            let flags = TINFL_FLAG_PARSE_ZLIB_HEADER | TINFL_FLAG_USING_NON_WRAPPING_OUTPUT_BUF;
-                            decompress(&mut decompressor, &deflated_data, &mut final_data, final_pos, flags);
-                            decompressor.init();
-                        }
+        let header = read_binary_header(&mut res, &resrc_type)?;
 
-                        final_pos += info.decompressed_size as usize;
-                    }
+        // once decrypted, the body is handled the same as an unencrypted one
+        let decrypted;
+        let (mut body, end) = match is_encrypted {
+            true => {
+                decrypted = decrypt_body(&mut res)?;
+                (Cursor::new(decrypted.as_slice()), decrypted.len() as u64)
+            },
+            false => {
+                let end = match header.dep_table_offset {
+                    Some(offset) => offset as u64,
+                    None => res.get_ref().len() as u64,
+                };
+                (res, end)
+            },
+        };
 
-                    ResrcMethod::Texture { data: final_data, gcm_info: gcm }
-                }
+        let data = match header.is_compressed {
+            true => decompress_chunks(&mut body)?,
+            false => {
+                let len = end.saturating_sub(body.position());
+                let mut data = Vec::new();
+                (&mut body).take(len).read_to_end(&mut data)?;
+                data
             },
-            _ => { ResrcMethod::Null },
         };
 
         Ok(Self {
             resrc_type,
-            method,
+            is_encrypted,
+            revision: header.revision,
+            compression_flags: header.compression_flags,
+            data,
         })
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use sha1::{Digest, Sha1};
+
+    // LBP2 (0x3f8) plan with compression flags 0x7, a SHA1 and a GUID dependency, and a
+    // 0x11234 byte body split into two deflated chunks and one stored chunk that didn't shrink
+    const PLAN_B: &[u8] = include_bytes!("../tests/fixtures/plan_b.bin");
+    const PLAN_BODY_SHA1: &str = "2b6429609e77890f61b4158be487d5564a26ab71";
+    // the same plan as an encrypted resource, with its chunk table and chunks xxtea encrypted.
+    // it was made with this crate's own xxtea::encrypt, so it only shows that decrypting undoes
+    // encrypting, not that real encrypted resources from the game decrypt correctly
+    const PLAN_E: &[u8] = include_bytes!("../tests/fixtures/plan_e.bin");
+
+    #[test]
+    fn multi_chunk_body() {
+        let body = ResrcBody::new(PLAN_B).unwrap();
+        assert_eq!(&body.resrc_type, b"PLN");
+        assert!(!body.is_encrypted);
+        assert_eq!(body.revision, ResrcRevision { head: 0x3f8, branch_id: 0, branch_revision: 0 });
+        assert_eq!(body.compression_flags, 0x7);
+        assert_eq!(body.data.len(), 0x11234);
+        assert_eq!(hex::encode(Sha1::digest(&body.data)), PLAN_BODY_SHA1);
+
+        let ResrcData { method: ResrcMethod::Binary { dependencies, .. }, .. } = ResrcData::new(PLAN_B, false).unwrap() else {
+            panic!("plan isn't a binary resource");
+        };
+        assert_eq!(dependencies.len(), 2);
+        assert_eq!(dependencies[1].desc, ResrcDescriptor::Guid(12345));
+    }
+
+    #[test]
+    fn encrypted_round_trip() {
+        let encrypted = ResrcBody::new(PLAN_E).unwrap();
+        assert!(encrypted.is_encrypted);
+        assert_eq!(hex::encode(Sha1::digest(&encrypted.data)), PLAN_BODY_SHA1);
+        assert_eq!(encrypted, ResrcBody { is_encrypted: true, ..ResrcBody::new(PLAN_B).unwrap() });
+
+        // the encrypted size right after the header is checked before anything gets allocated
+        let mut truncated = PLAN_E.to_vec();
+        truncated[20..24].copy_from_slice(&0x7fff_fff0u32.to_be_bytes());
+        assert!(matches!(ResrcBody::new(&truncated), Err(ResrcError::EncryptedTruncated)));
+    }
+
+    #[test]
+    fn newer_revisions() {
+        let rev = |s: &str| s.parse::<ResrcRevision>().unwrap();
+
+        // older LBP2 patches are readable by the latest one
+        assert!(!rev("0x3e0").is_newer_than(&GameVersion::Lbp2.get_latest_revision()));
+        assert!(rev("0x3f9").is_newer_than(&GameVersion::Lbp2.get_latest_revision()));
+
+        let lbp1 = GameVersion::Lbp1.get_latest_revision();
+        assert!(!rev("0x272").is_newer_than(&lbp1));
+        assert!(!rev("0x272:0x4c44:0x17").is_newer_than(&lbp1));
+        assert!(rev("0x272:0x4c44:0x18").is_newer_than(&lbp1));
+
+        // a branch can't be read by its head, or the other way around
+        assert!(rev("0x3e2:0x4431:0x87").is_newer_than(&rev("0x3e2")));
+        assert!(!rev("0x3e2").is_newer_than(&rev("0x3e2:0x4431:0x87")));
+
+        assert_eq!(rev("0x3e2:0x4431:0x87").to_string(), "0x3e2:0x4431:0x87");
+        assert_eq!(rev("lbp2").to_string(), "0x3f8");
+    }
+
+    #[test]
+    fn parse_revisions() {
+        let lbp1 = GameVersion::Lbp1.get_latest_revision();
+        assert_eq!("LBP1".parse(), Ok(lbp1));
+        assert_eq!("0x272:0x4c44:0x17".parse(), Ok(lbp1));
+        assert_eq!("0X272:0X4C44:17".parse(), Ok(lbp1));
+        assert_eq!("3f8".parse(), Ok(GameVersion::Lbp2.get_latest_revision()));
+        assert!("0x0x3f8".parse::<ResrcRevision>().is_err());
+        assert!("0x272:0x4c44".parse::<ResrcRevision>().is_err());
+        assert!("0x272:0x10000:0x0".parse::<ResrcRevision>().is_err());
+    }
+}
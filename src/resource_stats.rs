@@ -0,0 +1,212 @@
+use std::{collections::BTreeMap, fmt};
+
+use crate::{resource_parse::{ResrcData, ResrcMethod, ResrcRevision}, resource_type::ResourceType};
+
+// how many of the largest resources are listed per group
+const LARGEST_COUNT: usize = 3;
+const HISTOGRAM_WIDTH: usize = 40;
+
+/// Serialization method from the byte after the magic, textures are counted
+/// without being decompressed so this doesn't rely on [`ResrcMethod`] alone
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MethodGroup {
+    Binary,
+    Texture,
+    Null,
+}
+
+impl MethodGroup {
+    fn from_resource(resource: &[u8]) -> Self {
+        match resource.get(3) {
+            Some(b'b' | b'e') => Self::Binary,
+            Some(b' ') => Self::Texture,
+            _ => Self::Null,
+        }
+    }
+
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            Self::Binary => "binary",
+            Self::Texture => "texture",
+            Self::Null => "null",
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct GroupStats {
+    pub count: usize,
+    pub total_size: u64,
+    // largest first
+    pub largest: Vec<([u8; 20], u64)>,
+}
+
+impl GroupStats {
+    fn add(&mut self, sha1: [u8; 20], size: u64) {
+        self.count += 1;
+        self.total_size += size;
+
+        let pos = self.largest.partition_point(|(_, other)| *other >= size);
+        if pos < LARGEST_COUNT {
+            self.largest.insert(pos, (sha1, size));
+            self.largest.truncate(LARGEST_COUNT);
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ResourceStats {
+    pub total: GroupStats,
+    pub by_type: BTreeMap<[u8; 3], GroupStats>,
+    pub by_method: BTreeMap<MethodGroup, GroupStats>,
+    // binary resources only
+    pub revisions: BTreeMap<ResrcRevision, usize>,
+    // binary resources without a readable header, so they're not in the revisions
+    pub unparsed: usize,
+}
+
+impl ResourceStats {
+    pub fn new(resources: &BTreeMap<[u8; 20], Vec<u8>>) -> Self {
+        let mut stats = Self::default();
+
+        for (sha1, resource) in resources {
+            let size = resource.len() as u64;
+            let mut magic = [0u8; 3];
+            if let Some(m) = resource.first_chunk::<3>() {
+                magic = *m;
+            }
+
+            stats.total.add(*sha1, size);
+            stats.by_type.entry(magic).or_default().add(*sha1, size);
+            let method = MethodGroup::from_resource(resource);
+            stats.by_method.entry(method).or_default().add(*sha1, size);
+
+            if method == MethodGroup::Binary {
+                match ResrcData::new(resource, false) {
+                    Ok(ResrcData { method: ResrcMethod::Binary { revision, .. }, .. }) => {
+                        *stats.revisions.entry(revision).or_default() += 1;
+                    },
+                    _ => stats.unparsed += 1,
+                }
+            }
+        }
+
+        stats
+    }
+}
+
+fn format_size(size: u64) -> String {
+    match size {
+        0..1024 => format!("{size} B"),
+        1024..0x100000 => format!("{:.1} KB", size as f64 / 1024.0),
+        _ => format!("{:.2} MB", size as f64 / (1024.0 * 1024.0)),
+    }
+}
+
+fn format_magic(magic: &[u8; 3]) -> String {
+    let magic_str = magic.escape_ascii().to_string();
+    match ResourceType::from_magic(magic) {
+        Some(resrc_type) => format!("{magic_str} ({})", resrc_type.get_name()),
+        None => magic_str,
+    }
+}
+
+fn write_group(f: &mut fmt::Formatter, name: &str, group: &GroupStats) -> fmt::Result {
+    writeln!(f, "  {name}: {} resources, {}", group.count, format_size(group.total_size))?;
+    for (sha1, size) in &group.largest {
+        writeln!(f, "    {} {}", hex::encode(sha1), format_size(*size))?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for ResourceStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} resources, {} total", self.total.count, format_size(self.total.total_size))?;
+
+        writeln!(f, "By type:")?;
+        let mut by_type: Vec<_> = self.by_type.iter().collect();
+        by_type.sort_by_key(|(_, group)| std::cmp::Reverse(group.total_size));
+        for (magic, group) in by_type {
+            write_group(f, &format_magic(magic), group)?;
+        }
+
+        writeln!(f, "By method:")?;
+        for (method, group) in &self.by_method {
+            write_group(f, method.get_name(), group)?;
+        }
+
+        if !self.revisions.is_empty() || self.unparsed != 0 {
+            writeln!(f, "Binary revisions:")?;
+        }
+        if let Some(max) = self.revisions.values().max() {
+            for (revision, count) in &self.revisions {
+                let bar = "#".repeat((count * HISTOGRAM_WIDTH).div_ceil(*max));
+                writeln!(
+                    f,
+                    "  {:#x} (branch {:#x} rev {:#x}, {}): {bar} {count}",
+                    revision.head,
+                    revision.branch_id,
+                    revision.branch_revision,
+                    revision.get_gameversion().get_short_title(),
+                )?;
+            }
+        }
+        if self.unparsed != 0 {
+            writeln!(f, "  unparsed: {}", self.unparsed)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PLAN_B: &[u8] = include_bytes!("../tests/fixtures/plan_b.bin");
+    const PLAN_E: &[u8] = include_bytes!("../tests/fixtures/plan_e.bin");
+
+    #[test]
+    fn largest_in_order() {
+        let mut group = GroupStats::default();
+        for (i, size) in [5, 10, 5, 20, 10, 1, 10].into_iter().enumerate() {
+            group.add([i as u8; 20], size);
+        }
+        assert_eq!(group.count, 7);
+        assert_eq!(group.total_size, 61);
+        // ties keep the one added first
+        assert_eq!(group.largest, [([3; 20], 20), ([1; 20], 10), ([4; 20], 10)]);
+    }
+
+    #[test]
+    fn method_groups() {
+        assert_eq!(MethodGroup::from_resource(b"PLNb\0\0"), MethodGroup::Binary);
+        assert_eq!(MethodGroup::from_resource(b"PLNe\0\0"), MethodGroup::Binary);
+        assert_eq!(MethodGroup::from_resource(b"TEX \0\0"), MethodGroup::Texture);
+        assert_eq!(MethodGroup::from_resource(b"FSHx"), MethodGroup::Null);
+        assert_eq!(MethodGroup::from_resource(b"PLN"), MethodGroup::Null);
+        assert_eq!(MethodGroup::from_resource(b""), MethodGroup::Null);
+    }
+
+    #[test]
+    fn revision_histogram() {
+        let resources = BTreeMap::from([
+            ([1; 20], PLAN_B.to_vec()),
+            ([2; 20], PLAN_E.to_vec()),
+            ([3; 20], b"PLNb\0\0".to_vec()),
+            ([4; 20], b"TEX \0\0".to_vec()),
+        ]);
+        let stats = ResourceStats::new(&resources);
+
+        assert_eq!(stats.total.count, 4);
+        assert_eq!(stats.by_method[&MethodGroup::Binary].count, 3);
+        assert_eq!(stats.by_method[&MethodGroup::Texture].count, 1);
+        assert_eq!(stats.by_type[b"PLN"].count, 3);
+
+        let lbp2 = ResrcRevision { head: 0x3f8, branch_id: 0, branch_revision: 0 };
+        assert_eq!(stats.revisions, BTreeMap::from([(lbp2, 2)]));
+        // the truncated plan is still counted, just not in the histogram
+        assert_eq!(stats.unparsed, 1);
+        assert!(stats.to_string().contains("unparsed: 1"));
+    }
+}
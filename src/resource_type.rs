@@ -0,0 +1,258 @@
+use std::str::FromStr;
+
+// type ids, magics and extensions based on ResourceType from cwlib
+// https://github.com/ennuo/toolkit/tree/main/lib/cwlib
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ResourceType {
+    Texture,
+    GtfTexture,
+    GxtTexture,
+    Mesh,
+    PixelShader,
+    VertexShader,
+    Animation,
+    GuidSubstitution,
+    GfxMaterial,
+    SpuElf,
+    Level,
+    Filename,
+    Script,
+    SettingsCharacter,
+    FileOfBytes,
+    SettingsSoftPhys,
+    Fontface,
+    Material,
+    DownloadableContent,
+    EditorSettings,
+    Joint,
+    GameConstants,
+    PoppetSettings,
+    CachedLevelData,
+    SyncedProfile,
+    Bevel,
+    Game,
+    SettingsNetwork,
+    Packs,
+    BigProfile,
+    SlotList,
+    Translation,
+    AdventureCreateProfile,
+    LocalProfile,
+    LimitsSettings,
+    Tutorials,
+    GuidList,
+    AudioMaterials,
+    SettingsFluid,
+    Plan,
+    TextureList,
+    MusicSettings,
+    MixerSettings,
+    ReplayConfig,
+    Palette,
+    StaticMesh,
+    AnimatedTexture,
+    VoipRecording,
+    Pins,
+    Instrument,
+    Sample,
+    OutfitList,
+    Painting,
+    Quest,
+    AnimationBank,
+    AnimationSet,
+    SkeletonMap,
+    SkeletonRegistry,
+    SkeletonAnimStyles,
+    CrossplayVita,
+    StreamingChunk,
+    AdventureSharedData,
+    AdventurePlayProfile,
+    AnimationMap,
+    CachedCostumeData,
+    DataLabels,
+    AdventureMaps,
+}
+
+struct TypeInfo {
+    magic: Option<&'static [u8; 3]>,
+    id: u32,
+    name: &'static str,
+    extension: &'static str,
+}
+
+const fn info(magic: Option<&'static [u8; 3]>, id: u32, name: &'static str, extension: &'static str) -> TypeInfo {
+    TypeInfo { magic, id, name, extension }
+}
+
+impl ResourceType {
+    pub const ALL: [Self; 67] = [
+        Self::Texture, Self::GtfTexture, Self::GxtTexture, Self::Mesh, Self::PixelShader, Self::VertexShader,
+        Self::Animation, Self::GuidSubstitution, Self::GfxMaterial, Self::SpuElf, Self::Level,
+        Self::Filename, Self::Script, Self::SettingsCharacter, Self::FileOfBytes, Self::SettingsSoftPhys,
+        Self::Fontface, Self::Material, Self::DownloadableContent, Self::EditorSettings, Self::Joint,
+        Self::GameConstants, Self::PoppetSettings, Self::CachedLevelData, Self::SyncedProfile, Self::Bevel,
+        Self::Game, Self::SettingsNetwork, Self::Packs, Self::BigProfile, Self::SlotList,
+        Self::Translation, Self::AdventureCreateProfile, Self::LocalProfile, Self::LimitsSettings, Self::Tutorials,
+        Self::GuidList, Self::AudioMaterials, Self::SettingsFluid, Self::Plan, Self::TextureList,
+        Self::MusicSettings, Self::MixerSettings, Self::ReplayConfig, Self::Palette, Self::StaticMesh,
+        Self::AnimatedTexture, Self::VoipRecording, Self::Pins, Self::Instrument, Self::Sample,
+        Self::OutfitList, Self::Painting, Self::Quest, Self::AnimationBank, Self::AnimationSet,
+        Self::SkeletonMap, Self::SkeletonRegistry, Self::SkeletonAnimStyles, Self::CrossplayVita, Self::StreamingChunk,
+        Self::AdventureSharedData, Self::AdventurePlayProfile, Self::AnimationMap, Self::CachedCostumeData, Self::DataLabels,
+        Self::AdventureMaps,
+    ];
+
+    fn get_info(&self) -> TypeInfo {
+        match self {
+            Self::Texture => info(Some(b"TEX"), 1, "Texture", "tex"),
+            // gtf and gxt (vita) textures are referenced with the same type id as regular ones
+            Self::GtfTexture => info(Some(b"GTF"), 1, "GTF texture", "tex"),
+            Self::GxtTexture => info(Some(b"GXT"), 1, "GXT texture", "tex"),
+            Self::Mesh => info(Some(b"MSH"), 2, "Mesh", "mol"),
+            Self::PixelShader => info(None, 3, "Pixel shader", "fpo"),
+            Self::VertexShader => info(None, 4, "Vertex shader", "vpo"),
+            Self::Animation => info(Some(b"ANM"), 5, "Animation", "anim"),
+            Self::GuidSubstitution => info(Some(b"GSB"), 6, "GUID substitution", "gsub"),
+            Self::GfxMaterial => info(Some(b"GMT"), 7, "Gfx material", "gmat"),
+            Self::SpuElf => info(None, 8, "SPU ELF", "sbu"),
+            Self::Level => info(Some(b"LVL"), 9, "Level", "bin"),
+            Self::Filename => info(None, 10, "Filename", "txt"),
+            Self::Script => info(Some(b"FSH"), 11, "Script", "ff"),
+            Self::SettingsCharacter => info(Some(b"CHA"), 12, "Character settings", "cha"),
+            Self::FileOfBytes => info(None, 13, "File of bytes", "bin"),
+            Self::SettingsSoftPhys => info(Some(b"SSP"), 14, "Soft physics settings", "sph"),
+            Self::Fontface => info(Some(b"FNT"), 15, "Font face", "fnt"),
+            Self::Material => info(Some(b"MAT"), 16, "Physics material", "mat"),
+            Self::DownloadableContent => info(Some(b"DLC"), 17, "Downloadable content", "dlc"),
+            Self::EditorSettings => info(None, 18, "Editor settings", "edset"),
+            Self::Joint => info(Some(b"JNT"), 19, "Joint", "joint"),
+            Self::GameConstants => info(Some(b"CON"), 20, "Game constants", "con"),
+            Self::PoppetSettings => info(Some(b"POP"), 21, "Poppet settings", "pop"),
+            Self::CachedLevelData => info(Some(b"CLD"), 22, "Cached level data", "cld"),
+            Self::SyncedProfile => info(Some(b"PRF"), 23, "Synced profile", "pro"),
+            Self::Bevel => info(Some(b"BEV"), 24, "Bevel", "bev"),
+            Self::Game => info(Some(b"GAM"), 25, "Game", "gam"),
+            Self::SettingsNetwork => info(Some(b"NWS"), 26, "Network settings", "nws"),
+            Self::Packs => info(Some(b"PCK"), 27, "Packs", "pck"),
+            Self::BigProfile => info(Some(b"BPR"), 28, "Big profile", "bpr"),
+            Self::SlotList => info(Some(b"SLT"), 29, "Slot list", "slt"),
+            Self::Translation => info(None, 30, "Translation", "trans"),
+            Self::AdventureCreateProfile => info(Some(b"ADC"), 31, "Adventure", "adc"),
+            Self::LocalProfile => info(Some(b"IPR"), 32, "Local profile", "ipr"),
+            Self::LimitsSettings => info(Some(b"LMT"), 33, "Limits settings", "lmt"),
+            Self::Tutorials => info(Some(b"TUT"), 34, "Tutorials", "tut"),
+            Self::GuidList => info(Some(b"GLT"), 35, "GUID list", "glt"),
+            Self::AudioMaterials => info(Some(b"AUM"), 36, "Audio materials", "aum"),
+            Self::SettingsFluid => info(Some(b"SSF"), 37, "Fluid settings", "ssf"),
+            Self::Plan => info(Some(b"PLN"), 38, "Plan", "plan"),
+            Self::TextureList => info(Some(b"TXL"), 39, "Texture list", "tlist"),
+            Self::MusicSettings => info(Some(b"MUS"), 40, "Music settings", "mus"),
+            Self::MixerSettings => info(Some(b"MIX"), 41, "Mixer settings", "mix"),
+            Self::ReplayConfig => info(Some(b"REP"), 42, "Replay config", "rep"),
+            Self::Palette => info(Some(b"PAL"), 43, "Palette", "pal"),
+            Self::StaticMesh => info(Some(b"SMH"), 44, "Static mesh", "smh"),
+            Self::AnimatedTexture => info(Some(b"ATX"), 45, "Animated texture", "atx"),
+            Self::VoipRecording => info(Some(b"VOP"), 46, "Voice recording", "vop"),
+            Self::Pins => info(Some(b"PIN"), 47, "Pins", "pin"),
+            Self::Instrument => info(Some(b"INS"), 48, "Instrument", "rinst"),
+            Self::Sample => info(None, 49, "Sound sample", "smp"),
+            Self::OutfitList => info(Some(b"OFT"), 50, "Outfit list", "oft"),
+            Self::Painting => info(Some(b"PTG"), 51, "Painting", "ptg"),
+            Self::Quest => info(Some(b"QST"), 52, "Quest", "qst"),
+            Self::AnimationBank => info(Some(b"ABK"), 53, "Animation bank", "abnk"),
+            Self::AnimationSet => info(Some(b"AST"), 54, "Animation set", "aset"),
+            Self::SkeletonMap => info(Some(b"SMP"), 55, "Skeleton map", "smap"),
+            Self::SkeletonRegistry => info(Some(b"SRG"), 56, "Skeleton registry", "sreg"),
+            Self::SkeletonAnimStyles => info(Some(b"SAS"), 57, "Skeleton animation styles", "sas"),
+            Self::CrossplayVita => info(None, 58, "Cross-play Vita", "cpv"),
+            Self::StreamingChunk => info(Some(b"CHK"), 59, "Streaming chunk", "chk"),
+            Self::AdventureSharedData => info(Some(b"ADS"), 60, "Adventure shared data", "ads"),
+            Self::AdventurePlayProfile => info(Some(b"ADP"), 61, "Adventure play profile", "adp"),
+            Self::AnimationMap => info(Some(b"AMP"), 62, "Animation map", "amp"),
+            Self::CachedCostumeData => info(Some(b"CCD"), 63, "Cached costume data", "ccd"),
+            Self::DataLabels => info(Some(b"DLA"), 64, "Data labels", "dla"),
+            Self::AdventureMaps => info(Some(b"ADM"), 65, "Adventure maps", "adm"),
+        }
+    }
+
+    pub fn from_magic(magic: &[u8; 3]) -> Option<Self> {
+        Self::ALL.into_iter().find(|t| t.get_magic() == Some(magic))
+    }
+
+    /// Type from a dependency table id, textures (TEX, GTF and GXT) are always [`ResourceType::Texture`]
+    pub fn from_id(id: u32) -> Option<Self> {
+        Self::ALL.into_iter().find(|t| t.get_id() == id)
+    }
+
+    /// Looks up a type by its name or magic, ignoring case, spaces and underscores
+    pub fn from_name(name: &str) -> Option<Self> {
+        let normalize = |s: &str| s.replace([' ', '_', '-'], "").to_ascii_lowercase();
+        let name = normalize(name);
+        Self::ALL.into_iter().find(|t| {
+            normalize(t.get_name()) == name
+                || format!("{t:?}").to_ascii_lowercase() == name
+                || t.get_magic().is_some_and(|m| m.eq_ignore_ascii_case(name.as_bytes()))
+        })
+    }
+
+    pub fn get_magic(&self) -> Option<&'static [u8; 3]> {
+        self.get_info().magic
+    }
+
+    pub fn get_id(&self) -> u32 {
+        self.get_info().id
+    }
+
+    pub fn get_name(&self) -> &'static str {
+        self.get_info().name
+    }
+
+    /// Usual file extension, without the dot
+    pub fn get_extension(&self) -> &'static str {
+        self.get_info().extension
+    }
+}
+
+impl FromStr for ResourceType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_name(s).ok_or_else(|| format!("unknown resource type: {s}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookups_round_trip() {
+        for t in ResourceType::ALL {
+            if let Some(magic) = t.get_magic() {
+                assert_eq!(ResourceType::from_magic(magic), Some(t));
+                assert_eq!(ResourceType::from_name(&String::from_utf8_lossy(magic)), Some(t));
+            }
+
+            // textures share an id, which always comes back as a regular texture
+            match t {
+                ResourceType::GtfTexture | ResourceType::GxtTexture => {
+                    assert_eq!(ResourceType::from_id(t.get_id()), Some(ResourceType::Texture));
+                },
+                _ => assert_eq!(ResourceType::from_id(t.get_id()), Some(t)),
+            }
+
+            assert_eq!(ResourceType::from_name(t.get_name()), Some(t));
+            assert_eq!(ResourceType::from_name(&format!("{t:?}")), Some(t));
+            assert_eq!(format!("{t:?}").parse(), Ok(t));
+        }
+    }
+
+    #[test]
+    fn unknown_types() {
+        assert_eq!(ResourceType::from_magic(b"XYZ"), None);
+        assert_eq!(ResourceType::from_id(0), None);
+        assert_eq!(ResourceType::from_id(66), None);
+        assert_eq!(ResourceType::from_name("nothing"), None);
+    }
+}
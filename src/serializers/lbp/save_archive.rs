@@ -2,11 +2,10 @@ use std::{collections::BTreeMap, fs::File, io::Write, path::Path};
 
 use byteorder::{BigEndian, WriteBytesExt};
 use hmac::Mac;
-use anyhow::Result;
+use rayon::prelude::*;
 
-use crate::{resource_parse::ResrcRevision, serializers::HmacSha1, xxtea};
+use crate::{resource_parse::ResrcRevision, serializers::{HmacSha1, Result}, xxtea};
 
-const TEA_KEY: [u32; 4] = [0x1B70CBD, 0x149607D6, 0x7F94DD5, 0x10DB8CA0];
 const HASHINATE_KEY: [u8; 64] = [
     0x2A, 0xFD, 0xA3, 0xCA, 0x86, 0x02, 0x19, 0xB3,
     0xE6, 0x8A, 0xFF, 0xCC, 0x82, 0xC7, 0x6B, 0x8A,
@@ -83,17 +82,58 @@ pub fn make_savearchive(
     mac.update(&arc);
     (&mut arc[hashinate_offset..hashinate_offset + 0x14]).write_all(&mac.finalize().into_bytes())?;
 
+    // chunks are encrypted independently, so they can all be done at once
     let last_chunk_idx = arc.len() / CHUNK_SIZE;
-    for (i, chunk) in arc.chunks_mut(CHUNK_SIZE).enumerate() {
+    arc.par_chunks_mut(CHUNK_SIZE).enumerate().try_for_each(|(i, chunk)| -> Result<()> {
         let mut xxtea_end = chunk.len();
         if i == last_chunk_idx {
             xxtea_end -= 4;
         }
-        xxtea::encrypt(&TEA_KEY, &mut chunk[..xxtea_end]);
+        xxtea::encrypt(&xxtea::TEA_KEY, &mut chunk[..xxtea_end]);
 
         let mut file = File::create(bkp_dir.join(i.to_string()))?;
         file.write_all(chunk)?;
-    }
+        Ok(())
+    })?;
     
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn chunk_round_trip() {
+        let dir = std::env::temp_dir().join(format!("archive_dl_save_archive_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let rev = ResrcRevision { head: 0x3f8, branch_id: 0, branch_revision: 0 };
+        // odd length, so the archive gets padded before the save key
+        let resource: Vec<u8> = (0..1001).map(|i| i as u8).collect();
+        let mut hashes = BTreeMap::new();
+        hashes.insert([1; 20], resource.clone());
+        make_savearchive(&rev, [1; 20], hashes, &dir).unwrap();
+
+        let mut chunk = fs::read(dir.join("0")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        // the last 4 bytes are left unencrypted
+        let len = chunk.len();
+        assert_eq!(&chunk[len - 4..], b"FAR4");
+        assert_ne!(&chunk[..resource.len()], &resource[..]);
+
+        xxtea::decrypt(&xxtea::TEA_KEY, &mut chunk[..len - 4]);
+        assert_eq!(&chunk[..resource.len()], &resource[..]);
+        assert_eq!(&chunk[len - 8..len - 4], &1u32.to_be_bytes());
+
+        let hashinate_offset = len - 0x1c;
+        let mut plain = chunk.clone();
+        plain[hashinate_offset..hashinate_offset + 0x14].fill(0);
+        let mut mac = HmacSha1::new_from_slice(&HASHINATE_KEY).unwrap();
+        mac.update(&plain);
+        assert_eq!(&chunk[hashinate_offset..hashinate_offset + 0x14], mac.finalize().into_bytes().as_slice());
+    }
+}
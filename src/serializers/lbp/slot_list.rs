@@ -1,9 +1,8 @@
 use std::io::Write;
 
 use byteorder::{BigEndian, WriteBytesExt};
-use anyhow::Result;
 
-use crate::{db::{GameVersion, LevelType, SlotInfo}, labels::LBP2_LABELS, resource_parse::ResrcRevision, ResrcDescriptor};
+use crate::{db::{GameVersion, LevelType, SlotInfo}, labels::LBP2_LABELS, resource_parse::{ResrcDescriptor, ResrcRevision}, resource_type::ResourceType, serializers::{Result, SerializeError}};
 
 fn make_wstr(slt: &mut Vec<u8>, string: &str) -> Result<()> {
     let wide_string: Vec<u16> = string.encode_utf16().collect();
@@ -28,7 +27,9 @@ fn make_onlineid(slt: &mut Vec<u8>, rev: &ResrcRevision, np_handle: &str) -> Res
     }
 
     let mut data = [0u8; 16];
-    data[..np_handle.len()].copy_from_slice(np_handle.as_bytes());
+    data.get_mut(..np_handle.len())
+        .ok_or_else(|| SerializeError::NpHandleTooLong(np_handle.to_string()))?
+        .copy_from_slice(np_handle.as_bytes());
     slt.write_all(&data)?;
 
     slt.write_u8(0)?; // term
@@ -44,9 +45,9 @@ fn make_onlineid(slt: &mut Vec<u8>, rev: &ResrcRevision, np_handle: &str) -> Res
 fn make_res_descriptor(
     slt: &mut Vec<u8>,
     rev: &ResrcRevision,
-    deps: &mut Vec<(ResrcDescriptor, u32)>,
+    deps: &mut Vec<(ResrcDescriptor, ResourceType)>,
     desc: Option<ResrcDescriptor>,
-    resrc_type: u32
+    resrc_type: ResourceType
 ) -> Result<()> {
     let mut hash = 1;
     let mut guid = 2;
@@ -79,7 +80,7 @@ fn make_slot_struct(
     slt: &mut Vec<u8>,
     rev: &ResrcRevision,
     slot_info: &SlotInfo
-) -> Result<Vec<(ResrcDescriptor, u32)>> {
+) -> Result<Vec<(ResrcDescriptor, ResourceType)>> {
     let mut dependencies = Vec::new();
     let version = rev.get_version();
     let subversion = rev.get_subversion();
@@ -90,19 +91,19 @@ fn make_slot_struct(
 
     let root_desc = match slot_info.is_adventure_planet {
         true => None,
-        false => Some(ResrcDescriptor::Sha1(slot_info.root_level.as_slice().try_into()?))
+        false => Some(ResrcDescriptor::Sha1(slot_info.root_level))
     };
-    make_res_descriptor(slt, rev, &mut dependencies, root_desc, 9)?;
+    make_res_descriptor(slt, rev, &mut dependencies, root_desc, ResourceType::Level)?;
 
     if subversion >= 0x145 {
         let adventure_desc = match slot_info.is_adventure_planet {
-            true => Some(ResrcDescriptor::Sha1(slot_info.root_level.as_slice().try_into()?)),
+            true => Some(ResrcDescriptor::Sha1(slot_info.root_level)),
             false => None,
         };
-        make_res_descriptor(slt, rev, &mut dependencies, adventure_desc, 31)?;
+        make_res_descriptor(slt, rev, &mut dependencies, adventure_desc, ResourceType::AdventureCreateProfile)?;
     }
 
-    make_res_descriptor(slt, rev, &mut dependencies, Some(slot_info.icon), 1)?;
+    make_res_descriptor(slt, rev, &mut dependencies, Some(slot_info.icon), ResourceType::Texture)?;
 
     // location, this shouldn't matter
     for _ in 0..4 {
@@ -140,7 +141,7 @@ fn make_slot_struct(
     }
 
     if version > 0x333 {
-        make_res_descriptor(slt, rev, &mut dependencies, None, 38)?; // planetDecorations
+        make_res_descriptor(slt, rev, &mut dependencies, None, ResourceType::Plan)?; // planetDecorations
     }
 
     if version < 0x188 {
@@ -189,7 +190,7 @@ fn make_slot_struct(
     if version >= 0x2ea {
         slt.write_u32::<BigEndian>(3)?; // array count
         for _ in 0..3 {
-            make_res_descriptor(slt, rev, &mut dependencies, None, 38)?; // null plan descriptor
+            make_res_descriptor(slt, rev, &mut dependencies, None, ResourceType::Plan)?; // null plan descriptor
             slt.write_u32::<BigEndian>(0)?; // count
         }
     }
@@ -324,7 +325,7 @@ pub fn make_slotlist(rev: &ResrcRevision, slot_info: &SlotInfo) -> Result<Vec<u8
                     slt.write_u32::<BigEndian>(guid)?;
                 },
             }
-            slt.write_u32::<BigEndian>(resrc_type)?;
+            slt.write_u32::<BigEndian>(resrc_type.get_id())?;
         }
     }
 
@@ -1,7 +1,34 @@
-use hmac::Hmac;
+use hmac::{digest::InvalidLength, Hmac};
 use sha1::Sha1;
+use thiserror::Error;
 
 pub mod lbp;
 pub mod ps3;
 
-type HmacSha1 = Hmac<Sha1>;
\ No newline at end of file
+type HmacSha1 = Hmac<Sha1>;
+
+#[derive(Error, Debug)]
+pub enum SerializeError {
+    #[error("invalid hmac key length")]
+    HmacKey(#[from] InvalidLength),
+    #[error("npHandle is longer than 16 bytes: {0}")]
+    NpHandleTooLong(String),
+    #[error("SFO array is longer than its max size ({0} > {1})")]
+    SfoArrayTooLong(usize, u32),
+    #[error("invalid PARAM.SFO: {0}")]
+    SfoInvalid(&'static str),
+    #[error("{0} isn't a valid SFO {1}")]
+    SfoInvalidValue(String, &'static str),
+    #[error("{0} needs {1} bytes but only has room for {2}")]
+    SfoValueTooLong(String, usize, u32),
+    #[error("couldn't encrypt PARAM.PFD header")]
+    PfdEncrypt,
+    #[error("PARAM.PFD has a bad magic")]
+    PfdInvalid,
+    #[error("PARAM.PFD also protects {0}, re-signing it would drop those")]
+    PfdOtherFiles(String),
+    #[error("io error: {0}")]
+    IOError(#[from] std::io::Error),
+}
+
+type Result<T> = std::result::Result<T, SerializeError>;
\ No newline at end of file
@@ -1,5 +1,5 @@
 mod sfo;
 mod pfd;
 
-pub use sfo::make_sfo;
-pub use pfd::make_pfd;
\ No newline at end of file
+pub use sfo::{make_sfo, Sfo, SfoEntry, SfoValue};
+pub use pfd::{make_pfd, read_pfd, PfdInfo};
\ No newline at end of file
@@ -1,11 +1,10 @@
-use std::{fs::File, io::Write, path::Path};
+use std::{fs::File, io::{Read, Write}, path::Path};
 
 use aes::cipher::{block_padding::ZeroPadding, BlockEncryptMut, KeyIvInit};
-use byteorder::{BigEndian, WriteBytesExt};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 use hmac::{digest::{consts::U20, generic_array::GenericArray}, Mac};
-use anyhow::{anyhow, Result};
 
-use crate::serializers::HmacSha1;
+use crate::serializers::{HmacSha1, Result, SerializeError};
 
 // code based on
 // https://gitlab.com/osyu/slotmachine/-/blob/master/slotmachine/pfd.py
@@ -14,6 +13,8 @@ type Aes128CbcEnc = cbc::Encryptor<aes::Aes128>;
 
 const SYSCON_MANAGER_KEY: [u8; 16] = [0xd4, 0x13, 0xb8, 0x96, 0x63, 0xe1, 0xfe, 0x9f, 0x75, 0x14, 0x3d, 0x3b, 0xb4, 0x56, 0x52, 0x74];
 const KEYGEN_KEY: [u8; 20] = [0x6b, 0x1a, 0xce, 0xa2, 0x46, 0xb7, 0x45, 0xfd, 0x8f, 0x93, 0x76, 0x3b, 0x92, 0x05, 0x94, 0xcd, 0x53, 0x48, 0x3b, 0x82];
+// next index, file name, padding, key, 4 hashes, reserved, file size
+const PF_ENTRY_SIZE: usize = 8 + 65 + 7 + 64 + 4 * 20 + 40 + 8;
 const SAVEGAME_PARAM_SFO_KEY: [u8; 20] = [0x0c, 0x08, 0x00, 0x0e, 0x09, 0x05, 0x04, 0x04, 0x0d, 0x01, 0x0f, 0x00, 0x04, 0x06, 0x02, 0x02, 0x09, 0x06, 0x0d, 0x03];
 
 fn hmac_digest(key: &[u8], data: &[u8]) -> Result<GenericArray<u8, U20>> {
@@ -22,6 +23,50 @@ fn hmac_digest(key: &[u8], data: &[u8]) -> Result<GenericArray<u8, U20>> {
     Ok(hmac.finalize().into_bytes())
 }
 
+/// What an existing PARAM.PFD was made with
+pub struct PfdInfo {
+    /// 3, or 4 for LBP3 and later
+    pub version: u64,
+    /// names of the protected files
+    pub files: Vec<String>,
+}
+
+pub fn read_pfd(dir: &Path) -> Result<Option<PfdInfo>> {
+    let path = dir.join("PARAM.PFD");
+    if !path.exists() {
+        return Ok(None);
+    }
+    let mut file = File::open(path)?;
+    let mut magic = [0u8; 8];
+    file.read_exact(&mut magic)?;
+    if &magic != b"\0\0\0\0PFDB" {
+        return Err(SerializeError::PfdInvalid);
+    }
+    let version = file.read_u64::<BigEndian>()?;
+
+    // skip the header iv and the encrypted header
+    file.read_exact(&mut [0u8; 16 + 64])?;
+
+    let pf_index_size = file.read_u64::<BigEndian>()?;
+    file.read_u64::<BigEndian>()?; // reserved entries
+    let pf_entries_used = file.read_u64::<BigEndian>()?;
+    for _ in 0..pf_index_size {
+        file.read_u64::<BigEndian>()?;
+    }
+
+    // used entries are at the start of the entry table
+    let mut files = Vec::new();
+    for _ in 0..pf_entries_used {
+        let mut entry = [0u8; PF_ENTRY_SIZE];
+        file.read_exact(&mut entry)?;
+        let filename = &entry[8..8 + 65];
+        let filename = filename.split(|b| *b == 0).next().unwrap_or(filename);
+        files.push(String::from_utf8_lossy(filename).into_owned());
+    }
+
+    Ok(Some(PfdInfo { version, files }))
+}
+
 pub fn make_pfd(version: u64, sfo: Vec<u8>, dir: &Path) -> Result<()> {
     // these are normally random, but we can just null them out
     let pf_header_iv = [0u8; 16];
@@ -79,7 +124,7 @@ pub fn make_pfd(version: u64, sfo: Vec<u8>, dir: &Path) -> Result<()> {
 
     Aes128CbcEnc::new(&SYSCON_MANAGER_KEY.into(), &pf_header_iv.into())
         .encrypt_padded_mut::<ZeroPadding>(&mut pf_header, 64)
-        .map_err(|e| anyhow!(e))?;
+        .map_err(|_| SerializeError::PfdEncrypt)?;
 
     let mut file = File::create(dir.join("PARAM.PFD"))?;
 
@@ -94,4 +139,27 @@ pub fn make_pfd(version: u64, sfo: Vec<u8>, dir: &Path) -> Result<()> {
     file.write_all(&pf_entry_sig_table)?;
     
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_made_pfd() {
+        let dir = std::env::temp_dir().join(format!("archive_dl_read_made_pfd_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let missing = read_pfd(&dir);
+        let made = make_pfd(4, b"\0PSF".to_vec(), &dir);
+        let pfd = read_pfd(&dir);
+        // cleaned up before anything is checked, so a failure doesn't leave it behind
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(missing.unwrap().is_none());
+        made.unwrap();
+        let pfd = pfd.unwrap().unwrap();
+        assert_eq!(pfd.version, 4);
+        assert_eq!(pfd.files, ["PARAM.SFO"]);
+    }
+}
@@ -1,170 +1,335 @@
-use std::{fs::File, io::Write, path::Path};
+use std::{collections::BTreeMap, fmt, fs::File, io::{Cursor, Read, Write}, path::Path};
 
-use crate::db::{GameVersion, SlotInfo};
+use crate::{db::{GameVersion, SlotInfo}, serializers::{Result, SerializeError}};
 
-use byteorder::{LittleEndian, WriteBytesExt};
-use anyhow::Result;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 
-enum DataFormat<'a> {
-    Array(u32, &'a [u8]),
-    String(u32, &'a str),
+// format ids from the index table
+const FMT_ARRAY: u16 = 0x0004;
+const FMT_STRING: u16 = 0x0204;
+const FMT_INTEGER: u16 = 0x0404;
+
+const HEADER_SIZE: usize = 0x14;
+const INDEX_ENTRY_SIZE: usize = 0x10;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SfoValue {
+    Array(Vec<u8>),
+    String(String),
     Integer(u32),
 }
 
-impl DataFormat<'_> {
-    fn get_fmt_id(&self) -> [u8; 2] {
+impl SfoValue {
+    fn get_fmt_id(&self) -> u16 {
         match self {
-            Self::Array(..) => [0x04, 0x00],
-            Self::String(..) => [0x04, 0x02],
-            Self::Integer(..) => [0x04, 0x04],
+            Self::Array(..) => FMT_ARRAY,
+            Self::String(..) => FMT_STRING,
+            Self::Integer(..) => FMT_INTEGER,
         }
     }
 
-    fn get_data(&self) -> Vec<u8> {
+    pub fn get_type_name(&self) -> &'static str {
         match self {
-            Self::Array(max, a) => {
-                assert!(a.len() as u32 <= *max);
-                a.to_vec()
+            Self::Array(..) => "array",
+            Self::String(..) => "string",
+            Self::Integer(..) => "integer",
+        }
+    }
+
+    fn get_data(&self, max: u32) -> Result<Vec<u8>> {
+        Ok(match self {
+            Self::Array(a) => {
+                if a.len() > max as usize {
+                    return Err(SerializeError::SfoArrayTooLong(a.len(), max));
+                }
+                a.clone()
             },
-            Self::String(max, s) => {
-                if s.len() >= *max as usize {
-                    format!("{}...\0", s.split_at(*max as usize - 4).0)
+            Self::String(s) => {
+                let max = max as usize;
+                if max == 0 {
+                    Vec::new()
+                } else if s.len() >= max {
+                    // cut off with an ellipsis if there's room for one
+                    let ellipsis = if max >= 4 { "..." } else { "" };
+                    // don't split in the middle of a multi-byte character
+                    let mut end = max - ellipsis.len() - 1;
+                    while !s.is_char_boundary(end) {
+                        end -= 1;
+                    }
+                    format!("{}{ellipsis}\0", &s[..end]).into_bytes()
                 } else {
-                    format!("{s}\0")
-                }.as_bytes().to_vec()
+                    format!("{s}\0").into_bytes()
+                }
             },
             Self::Integer(i) => i.to_le_bytes().to_vec(),
+        })
+    }
+
+    /// Size of the value in the data table, strings include the null terminator
+    pub fn get_size(&self) -> usize {
+        match self {
+            Self::Array(a) => a.len(),
+            Self::String(s) => s.len() + 1,
+            Self::Integer(..) => 4,
         }
     }
 
-    fn get_max_size(&self) -> u32 {
+    /// Parses a new value of the same type, integers as decimal or 0x hex and arrays as hex
+    pub fn parse_like(&self, value: &str) -> Result<Self> {
+        let invalid = || SerializeError::SfoInvalidValue(value.to_string(), self.get_type_name());
+        Ok(match self {
+            Self::Array(..) => Self::Array(hex::decode(value).map_err(|_| invalid())?),
+            Self::String(..) => Self::String(value.to_string()),
+            Self::Integer(..) => Self::Integer(match value.strip_prefix("0x") {
+                Some(hex) => u32::from_str_radix(hex, 16),
+                None => value.parse(),
+            }.map_err(|_| invalid())?),
+        })
+    }
+}
+
+impl fmt::Display for SfoValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            DataFormat::Array(max, _) => *max,
-            DataFormat::String(max, _) => *max,
-            DataFormat::Integer(_) => 4,
+            Self::Array(a) => write!(f, "{}", hex::encode(a)),
+            Self::String(s) => write!(f, "{s:?}"),
+            Self::Integer(i) => write!(f, "{i} ({i:#x})"),
         }
     }
 }
 
-struct IndexEntry<'a> {
-    key: &'a str,
-    data: DataFormat<'a>,
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SfoEntry {
+    pub value: SfoValue,
+    // space reserved for the value in the data table
+    pub max_size: u32,
 }
 
-const ENTRIES_LEN: usize = 10;
+/// PARAM.SFO contents, keys are kept sorted since the table has to be in that order
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Sfo {
+    pub entries: BTreeMap<String, SfoEntry>,
+}
 
-pub fn make_sfo(slot_info: &SlotInfo, display_name: &str, bkp_name: &str, dir: &Path, gamever: &GameVersion) -> Result<Vec<u8>> {
-    let title = match slot_info.is_adventure_planet {
-        false => format!("{} Dry Archive Level Backup", gamever.get_title()),
-        true => format!("{} Dry Archive Adventure Backup", gamever.get_title()),
-    };
-    let subtitle = format!("{display_name} by {}", slot_info.np_handle);
+impl Sfo {
+    pub fn parse(sfo: &[u8]) -> Result<Self> {
+        let mut cur = Cursor::new(sfo);
+
+        let mut magic = [0u8; 4];
+        cur.read_exact(&mut magic)?;
+        if &magic != b"\0PSF" {
+            return Err(SerializeError::SfoInvalid("bad magic"));
+        }
+        cur.read_u32::<LittleEndian>()?; // version
+        let key_table_offset = cur.read_u32::<LittleEndian>()? as usize;
+        let data_table_offset = cur.read_u32::<LittleEndian>()? as usize;
+        let count = cur.read_u32::<LittleEndian>()? as usize;
+
+        let mut entries = BTreeMap::new();
+        for _ in 0..count {
+            let key_offset = cur.read_u16::<LittleEndian>()? as usize;
+            let fmt_id = cur.read_u16::<LittleEndian>()?;
+            let size = cur.read_u32::<LittleEndian>()? as usize;
+            let max_size = cur.read_u32::<LittleEndian>()?;
+            let data_offset = cur.read_u32::<LittleEndian>()? as usize;
+
+            let key = sfo.get(key_table_offset + key_offset..)
+                .and_then(|keys| keys.split(|b| *b == 0).next())
+                .ok_or(SerializeError::SfoInvalid("key out of bounds"))?;
+            let key = String::from_utf8(key.to_vec())
+                .map_err(|_| SerializeError::SfoInvalid("key isn't utf-8"))?;
+
+            let start = data_table_offset + data_offset;
+            let data = sfo.get(start..start + size)
+                .ok_or(SerializeError::SfoInvalid("data out of bounds"))?;
 
-    // these need to be in alphabetical order
-    let entries: [IndexEntry; ENTRIES_LEN] = [
-        IndexEntry {
-            key: "ACCOUNT_ID",
-            data: DataFormat::Array(16, b"0000000000000000")
-        },
-        IndexEntry {
-            key: "ATTRIBUTE",
-            data: DataFormat::Integer(0)
-        },
-        IndexEntry {
-            key: "CATEGORY",
-            data: DataFormat::String(4, "SD")
-        },
-        IndexEntry {
-            key: "DETAIL",
-            data: DataFormat::String(1024, &slot_info.description)
-        },
-        IndexEntry {
-            key: "PARAMS",
-            data: DataFormat::Array(1024, &[0u8; 1024])
-        },
-        IndexEntry {
-            key: "PARAMS2",
-            data: DataFormat::Array(1024, &[0u8; 12])
-        },
-        IndexEntry {
-            key: "SAVEDATA_DIRECTORY",
-            data: DataFormat::String(64, bkp_name)
-        },
-        IndexEntry {
-            key: "SAVEDATA_LIST_PARAM",
-            data: DataFormat::String(8, "")
-        },
-        IndexEntry {
-            key: "SUB_TITLE",
-            data: DataFormat::String(128, &subtitle)
-        },
-        IndexEntry {
-            key: "TITLE",
-            data: DataFormat::String(128, &title)
-        },
-    ];
-
-    let mut key_offsets = [0; ENTRIES_LEN];
-    let mut key_table = Vec::new();
-
-    for (i, entry) in entries.iter().enumerate() {
-        key_offsets[i] = key_table.len() as u16;
-        key_table.write_all(entry.key.as_bytes())?;
-        key_table.write_u8(0)?; // null terminator
-    }
-
-    let mut data_info = [(0, 0); ENTRIES_LEN];
-    let mut data_table = Vec::new();
-    for (i, entry) in entries.iter().enumerate() {
-        let data = entry.data.get_data();
-        let size = data.len() as u32;
-        let offset = data_table.len() as u32;
-
-        data_table.write_all(&data)?;
-        
-        let pad = entry.data.get_max_size() - size;
-        if pad != 0 {
-            data_table.write_all(&b"\0".repeat(pad as usize))?;
+            let value = match fmt_id {
+                FMT_STRING => {
+                    let data = data.strip_suffix(b"\0").unwrap_or(data);
+                    SfoValue::String(String::from_utf8_lossy(data).into_owned())
+                },
+                FMT_INTEGER => {
+                    let data = data.try_into().map_err(|_| SerializeError::SfoInvalid("integer isn't 4 bytes"))?;
+                    SfoValue::Integer(u32::from_le_bytes(data))
+                },
+                // anything else is kept as raw bytes
+                _ => SfoValue::Array(data.to_vec()),
+            };
+            entries.insert(key, SfoEntry { value, max_size });
         }
 
-        data_info[i] = (size, offset);
+        Ok(Self { entries })
     }
 
-    let mut sfo = Vec::new();
+    pub fn get(&self, key: &str) -> Option<&SfoValue> {
+        self.entries.get(key).map(|entry| &entry.value)
+    }
 
-    sfo.write_all(b"\0PSF")?;
-    sfo.write_all(&[0x01, 0x01, 0x00, 0x00])?; // version 1.1
-    sfo.write_u32::<LittleEndian>(0)?; // key table offset, to be written later
-    sfo.write_u32::<LittleEndian>(0)?; // data table offset, to be written later
-    sfo.write_u32::<LittleEndian>(ENTRIES_LEN as u32)?;
+    /// Sets a value, an existing entry keeps its max size unless the new value needs more
+    pub fn set(&mut self, key: &str, value: SfoValue, max_size: u32) {
+        let max_size = match self.entries.get(key) {
+            Some(entry) => entry.max_size.max(max_size),
+            None => max_size,
+        };
+        self.entries.insert(key.to_string(), SfoEntry { value, max_size });
+    }
 
-    // index table
-    for (i, entry) in entries.iter().enumerate() {
-        sfo.write_u16::<LittleEndian>(key_offsets[i])?;
-        sfo.write_all(&entry.data.get_fmt_id())?;
-        sfo.write_u32::<LittleEndian>(data_info[i].0)?; // data size
-        sfo.write_u32::<LittleEndian>(entry.data.get_max_size())?;
-        sfo.write_u32::<LittleEndian>(data_info[i].1)?; // data offset
+    /// Sets a value without truncating it, an existing entry has to have room for it
+    /// and a new one gets just enough
+    pub fn try_set(&mut self, key: &str, value: SfoValue) -> Result<()> {
+        let size = value.get_size();
+        let max_size = match self.entries.get(key) {
+            Some(entry) if size > entry.max_size as usize => {
+                return Err(SerializeError::SfoValueTooLong(key.to_string(), size, entry.max_size));
+            },
+            Some(entry) => entry.max_size,
+            None => size.next_multiple_of(4) as u32,
+        };
+        self.entries.insert(key.to_string(), SfoEntry { value, max_size });
+        Ok(())
     }
 
-    let key_table_offset = sfo.len();
-    sfo.write_all(&key_table)?;
+    pub fn remove(&mut self, key: &str) -> Option<SfoValue> {
+        self.entries.remove(key).map(|entry| entry.value)
+    }
+
+    pub fn serialize(&self) -> Result<Vec<u8>> {
+        let mut key_table = Vec::new();
+        let mut data_table = Vec::new();
+        let mut index_table = Vec::new();
+
+        for (key, entry) in &self.entries {
+            let data = entry.value.get_data(entry.max_size)?;
+
+            index_table.write_u16::<LittleEndian>(key_table.len() as u16)?;
+            index_table.write_u16::<LittleEndian>(entry.value.get_fmt_id())?;
+            index_table.write_u32::<LittleEndian>(data.len() as u32)?; // data size
+            index_table.write_u32::<LittleEndian>(entry.max_size)?;
+            index_table.write_u32::<LittleEndian>(data_table.len() as u32)?; // data offset
 
-    // align to 4 byte boundary
-    let mut pad = sfo.len() % 4;
-    if pad != 0 {
-        pad = 4 - pad;
+            key_table.write_all(key.as_bytes())?;
+            key_table.write_u8(0)?; // null terminator
+
+            data_table.write_all(&data)?;
+            data_table.resize(data_table.len() + (entry.max_size as usize).saturating_sub(data.len()), 0);
+        }
+
+        // align to 4 byte boundary
+        key_table.resize(key_table.len().next_multiple_of(4), 0);
+
+        let key_table_offset = HEADER_SIZE + self.entries.len() * INDEX_ENTRY_SIZE;
+        let data_table_offset = key_table_offset + key_table.len();
+
+        let mut sfo = Vec::new();
+        sfo.write_all(b"\0PSF")?;
+        sfo.write_all(&[0x01, 0x01, 0x00, 0x00])?; // version 1.1
+        sfo.write_u32::<LittleEndian>(key_table_offset as u32)?;
+        sfo.write_u32::<LittleEndian>(data_table_offset as u32)?;
+        sfo.write_u32::<LittleEndian>(self.entries.len() as u32)?;
+        sfo.write_all(&index_table)?;
+        sfo.write_all(&key_table)?;
+        sfo.write_all(&data_table)?;
+
+        Ok(sfo)
+    }
+}
+
+pub(crate) fn serialize_sfo(slot_info: &SlotInfo, display_name: &str, detail: &str, bkp_name: &str, gamever: &GameVersion, is_partial: bool) -> Result<Vec<u8>> {
+    let mut title = match slot_info.is_adventure_planet {
+        false => format!("{} Dry Archive Level Backup", gamever.get_title()),
+        true => format!("{} Dry Archive Adventure Backup", gamever.get_title()),
+    };
+    // some resources were left out on purpose, so make that obvious in the save menu
+    if is_partial {
+        title.push_str(" (Partial)");
     }
-    sfo.write_all(&b"\0".repeat(pad))?;
+    let subtitle = format!("{display_name} by {}", slot_info.np_handle);
+
+    let mut sfo = Sfo::default();
+    sfo.set("ACCOUNT_ID", SfoValue::Array(b"0000000000000000".to_vec()), 16);
+    sfo.set("ATTRIBUTE", SfoValue::Integer(0), 4);
+    sfo.set("CATEGORY", SfoValue::String("SD".to_string()), 4);
+    sfo.set("DETAIL", SfoValue::String(detail.to_string()), 1024);
+    sfo.set("PARAMS", SfoValue::Array(vec![0u8; 1024]), 1024);
+    sfo.set("PARAMS2", SfoValue::Array(vec![0u8; 12]), 1024);
+    sfo.set("SAVEDATA_DIRECTORY", SfoValue::String(bkp_name.to_string()), 64);
+    sfo.set("SAVEDATA_LIST_PARAM", SfoValue::String(String::new()), 8);
+    sfo.set("SUB_TITLE", SfoValue::String(subtitle), 128);
+    sfo.set("TITLE", SfoValue::String(title), 128);
 
-    let data_table_offset = sfo.len();
-    sfo.write_all(&data_table)?;
+    sfo.serialize()
+}
 
-    (&mut sfo[8..12]).write_u32::<LittleEndian>(key_table_offset as u32)?;
-    (&mut sfo[12..16]).write_u32::<LittleEndian>(data_table_offset as u32)?;
+pub fn make_sfo(slot_info: &SlotInfo, display_name: &str, detail: &str, bkp_name: &str, dir: &Path, gamever: &GameVersion, is_partial: bool) -> Result<Vec<u8>> {
+    let sfo = serialize_sfo(slot_info, display_name, detail, bkp_name, gamever, is_partial)?;
 
     let mut file = File::create(dir.join("PARAM.SFO"))?;
     file.write_all(&sfo)?;
 
     Ok(sfo)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{db::LevelType, resource_parse::ResrcDescriptor};
+
+    fn slot_info() -> SlotInfo {
+        SlotInfo {
+            name: "Sackboy's Level".to_string(),
+            description: "A level".to_string(),
+            np_handle: "Sackboy".to_string(),
+            root_level: [0; 20],
+            icon: ResrcDescriptor::Guid(0),
+            game: GameVersion::Lbp2,
+            initially_locked: false,
+            is_sub_level: false,
+            background_guid: None,
+            shareable: false,
+            author_labels: Vec::new(),
+            leveltype: LevelType::Cooperative,
+            min_players: None,
+            max_players: None,
+            is_adventure_planet: false,
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let detail = "x".repeat(2000);
+        let sfo = serialize_sfo(&slot_info(), "Sackboy's Level", &detail, "BCUS98245LEVEL0000002A", &GameVersion::Lbp2, false).unwrap();
+        let parsed = Sfo::parse(&sfo).unwrap();
+        assert_eq!(parsed.get("SAVEDATA_DIRECTORY"), Some(&SfoValue::String("BCUS98245LEVEL0000002A".to_string())));
+        assert_eq!(parsed.get("ATTRIBUTE"), Some(&SfoValue::Integer(0)));
+        assert_eq!(parsed.entries["DETAIL"].max_size, 1024);
+        assert_eq!(parsed.serialize().unwrap(), sfo);
+    }
+
+    #[test]
+    fn truncated_strings_fit() {
+        let long = SfoValue::String("abcdefgh".to_string());
+        for max in 0..10 {
+            let data = long.get_data(max).unwrap();
+            assert!(data.len() <= max as usize);
+        }
+        assert_eq!(long.get_data(3).unwrap(), b"ab\0");
+        assert_eq!(long.get_data(8).unwrap(), b"abcd...\0");
+        // the ellipsis doesn't split the multi-byte character
+        assert_eq!(SfoValue::String("éabc".to_string()).get_data(5).unwrap(), b"...\0");
+    }
+
+    #[test]
+    fn try_set_keeps_max_size() {
+        let mut sfo = Sfo::default();
+        sfo.set("SAVEDATA_DIRECTORY", SfoValue::String("BCUS98245LEVEL0000002A".to_string()), 24);
+        sfo.try_set("SAVEDATA_DIRECTORY", SfoValue::String("BCES00850LEVEL0000002A".to_string())).unwrap();
+        assert!(matches!(
+            sfo.try_set("SAVEDATA_DIRECTORY", SfoValue::String("BCES00850LEVEL0000002ABC".to_string())),
+            Err(SerializeError::SfoValueTooLong(_, 25, 24)),
+        ));
+        assert_eq!(sfo.get("SAVEDATA_DIRECTORY"), Some(&SfoValue::String("BCES00850LEVEL0000002A".to_string())));
+
+        sfo.try_set("NEW", SfoValue::String("abcd".to_string())).unwrap();
+        assert_eq!(sfo.entries["NEW"].max_size, 8);
+    }
+}
@@ -0,0 +1,168 @@
+use std::{collections::BTreeMap, fs::{self, File}, path::Path};
+
+use crate::{gtf_texture::decode_texture, resource_parse::{ResrcData, ResrcMethod}};
+
+use image::{DynamicImage, ImageFormat};
+use serde::Serialize;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ExportError {
+    #[error("Couldn't write {0}: {1}")]
+    Write(String, image::ImageError),
+    #[error("yaml error: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+    #[error("io error: {0}")]
+    IOError(#[from] std::io::Error),
+}
+
+type Result<T> = std::result::Result<T, ExportError>;
+
+const DDS_FOURCC: u32 = 0x4;
+const DDS_SURFACE_FLAGS_CUBEMAP: u32 = 0x00000200;
+
+#[derive(Serialize)]
+struct SurfaceEntry {
+    file: String,
+    face: u8,
+    level: u8,
+    width: u32,
+    height: u32,
+}
+
+#[derive(Serialize)]
+struct ManifestEntry {
+    sha1: String,
+    #[serde(rename = "type")]
+    resrc_type: String,
+    format: String,
+    width: u32,
+    height: u32,
+    mipmaps: u32,
+    cubemap: bool,
+    surfaces: Vec<SurfaceEntry>,
+}
+
+#[derive(Serialize)]
+struct Manifest {
+    textures: Vec<ManifestEntry>,
+    // textures that weren't downloaded since they're in the game data
+    skipped: Vec<String>,
+}
+
+pub struct ExportStats {
+    pub exported: usize,
+    pub surfaces: usize,
+    pub failed: usize,
+    pub skipped: usize,
+}
+
+fn read_u32_le(data: &[u8], offset: usize) -> u32 {
+    let mut bytes = [0u8; 4];
+    if let Some(slice) = data.get(offset..offset + 4) {
+        bytes.copy_from_slice(slice);
+    }
+    u32::from_le_bytes(bytes)
+}
+
+// format, mipmap count and cubemap flag from a DDS header
+fn get_dds_info(dds: &[u8]) -> (String, u32, bool) {
+    let format = match read_u32_le(dds, 80) & DDS_FOURCC != 0 {
+        true => String::from_utf8_lossy(&read_u32_le(dds, 84).to_le_bytes()).into_owned(),
+        false => format!("{}bpp", read_u32_le(dds, 88)),
+    };
+    let mipmaps = read_u32_le(dds, 28).max(1);
+    let cubemap = read_u32_le(dds, 112) & DDS_SURFACE_FLAGS_CUBEMAP != 0;
+    (format, mipmaps, cubemap)
+}
+
+/// Decodes every TEX/GTF resource to PNGs named `{sha1}_f{face}_m{level}.png`,
+/// alongside a manifest.yml listing their surfaces, dimensions and formats.
+/// GTF textures get every face and mip level, TEX (DDS) textures only the first surface
+pub fn export_textures(resources: &BTreeMap<[u8; 20], Vec<u8>>, skipped: &[[u8; 20]], dir: &Path) -> Result<ExportStats> {
+    fs::create_dir_all(dir)?;
+
+    let mut textures = Vec::new();
+    let mut failed = 0;
+
+    for (hash, resource) in resources {
+        let resrc_type = match resource.get(..3) {
+            Some(b"TEX") | Some(b"GTF") => String::from_utf8_lossy(&resource[..3]).into_owned(),
+            _ => continue,
+        };
+        let sha1 = hex::encode(hash);
+
+        let (data, gcm_info) = match ResrcData::new(resource, true) {
+            Ok(ResrcData { method: ResrcMethod::Texture { data, gcm_info }, .. }) => (data, gcm_info),
+            Ok(_) => continue,
+            Err(error) => {
+                eprintln!("WARNING: Couldn't parse texture {sha1}: {error}");
+                failed += 1;
+                continue;
+            },
+        };
+
+        // (face, level, image)
+        let decoded = match &gcm_info {
+            Some(gcm) => gcm.decode(&data).map(|surfaces| {
+                surfaces.into_iter()
+                    .map(|surface| (surface.face, surface.level, DynamicImage::ImageRgba8(surface.image)))
+                    .collect::<Vec<_>>()
+            }),
+            None => decode_texture(&data, None).map(|img| vec![(0, 0, img)]),
+        };
+        let decoded = match decoded {
+            Ok(decoded) => decoded,
+            Err(error) => {
+                eprintln!("WARNING: Couldn't decode texture {sha1}: {error}");
+                failed += 1;
+                continue;
+            },
+        };
+
+        let mut surfaces = Vec::with_capacity(decoded.len());
+        for (face, level, img) in decoded {
+            let file = format!("{sha1}_f{face}_m{level}.png");
+            let path = dir.join(&file);
+            img.save_with_format(&path, ImageFormat::Png)
+                .map_err(|e| ExportError::Write(path.display().to_string(), e))?;
+            surfaces.push(SurfaceEntry {
+                file,
+                face,
+                level,
+                width: img.width(),
+                height: img.height(),
+            });
+        }
+
+        let (format, mipmaps, cubemap) = match &gcm_info {
+            Some(gcm) => (format!("{:?}", gcm.format), gcm.get_level_count().into(), gcm.cubemap != 0),
+            None => get_dds_info(&data),
+        };
+
+        textures.push(ManifestEntry {
+            sha1,
+            resrc_type,
+            format,
+            width: surfaces[0].width,
+            height: surfaces[0].height,
+            mipmaps,
+            cubemap,
+            surfaces,
+        });
+    }
+
+    let manifest = Manifest {
+        textures,
+        skipped: skipped.iter().map(hex::encode).collect(),
+    };
+    let file = File::create(dir.join("manifest.yml"))?;
+    serde_yaml::to_writer(file, &manifest)?;
+
+    Ok(ExportStats {
+        exported: manifest.textures.len(),
+        surfaces: manifest.textures.iter().map(|texture| texture.surfaces.len()).sum(),
+        failed,
+        skipped: manifest.skipped.len(),
+    })
+}
\ No newline at end of file
@@ -1,38 +1,127 @@
-use core::slice;
-
 // modified code from https://github.com/mgottschlag/xxtea-nostd
 
 // The code is based on the public domain implementation at
 // https://github.com/mycelium-com/entropy/blob/master/lib/xxtea.c
 
-fn as_u32_slice_mut(x: &mut [u8]) -> &mut [u32] {
-    // Safe, because the length is rounded down.
-    unsafe { slice::from_raw_parts_mut(x.as_mut_ptr() as *mut u32, x.len() / 4) }
+// blocks are big endian u32 words read straight from the byte slice,
+// so the buffer doesn't need to be aligned to anything
+
+const DELTA: u32 = 0x9e3779b9;
+
+/// Key used by LBP for both save archive chunks and encrypted (`e`) resources
+pub const TEA_KEY: [u32; 4] = [0x1B70CBD, 0x149607D6, 0x7F94DD5, 0x10DB8CA0];
+
+fn load(block: &[u8], i: usize) -> u32 {
+    let mut word = [0u8; 4];
+    word.copy_from_slice(&block[i * 4..i * 4 + 4]);
+    u32::from_be_bytes(word)
 }
 
-pub fn encrypt(key: &[u32], block: &mut [u8]) {
-    assert_eq!(key.len(), 4);
-    assert_eq!(block.len() & 3, 0);
+fn store(block: &mut [u8], i: usize, value: u32) {
+    block[i * 4..i * 4 + 4].copy_from_slice(&value.to_be_bytes());
+}
+
+fn mx(key: &[u32; 4], sum: u32, y: u32, z: u32, r: usize) -> u32 {
+    let e = (sum >> 2) as usize;
+    (((z >> 5) ^ (y << 2)).wrapping_add((y >> 3) ^ (z << 4)))
+        ^ ((sum ^ y).wrapping_add(key[(r ^ e) & 3] ^ z))
+}
 
-    let block = as_u32_slice_mut(block);
+/// Encrypts `block` in place. Trailing bytes that don't make up a full word are left untouched.
+/// XXTEA isn't defined for single word blocks, but they're still run through the rounds
+/// like the save archive code has always done, so the last chunk of an archive doesn't change.
+pub fn encrypt(key: &[u32; 4], block: &mut [u8]) {
+    let len = block.len() / 4;
+    if len == 0 {
+        return;
+    }
 
-    let rounds = 6 + 52 / block.len();
-    let n = block.len() - 1;
+    let rounds = 6 + 52 / len;
 
     let mut sum = 0u32;
-    let mut z = u32::from_be(block[n]); // left neighbour for the first round
+    let mut z = load(block, len - 1); // left neighbour for the first round
     for _ in 0..rounds {
         // cycle
-        sum = sum.wrapping_add(0x9e3779b9);
-        let e = sum >> 2;
-        for r in 0..block.len() {
+        sum = sum.wrapping_add(DELTA);
+        for r in 0..len {
+            // round
+            let y = load(block, (r + 1) % len); // right neighbour
+            z = load(block, r).wrapping_add(mx(key, sum, y, z, r));
+            store(block, r, z); // left neighbour for the next round
+        }
+    }
+}
+
+/// Decrypts `block` in place, undoing [`encrypt`] with the same key.
+/// Single word blocks are left as they are, encrypting those can't be undone.
+pub fn decrypt(key: &[u32; 4], block: &mut [u8]) {
+    let len = block.len() / 4;
+    if len < 2 {
+        return;
+    }
+
+    let rounds = 6 + 52 / len;
+
+    let mut sum = (rounds as u32).wrapping_mul(DELTA);
+    let mut y = load(block, 0); // right neighbour for the first round
+    for _ in 0..rounds {
+        // cycle, in reverse
+        for r in (0..len).rev() {
             // round
-            let y = u32::from_be(block[(r + 1) % block.len()]); // right neighbour
-            block[r] = u32::to_be(u32::from_be(block[r]).wrapping_add(
-                (((z >> 5) ^ (y << 2)).wrapping_add((y >> 3) ^ (z << 4)))
-                    ^ ((sum ^ y).wrapping_add(key[(r ^ e as usize) & 3] ^ z)),
-            ));
-            z = u32::from_be(block[r]); // left neighbour for the next round
+            let z = load(block, (r + len - 1) % len); // left neighbour
+            y = load(block, r).wrapping_sub(mx(key, sum, y, z, r));
+            store(block, r, y); // right neighbour for the next round
+        }
+        sum = sum.wrapping_sub(DELTA);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn to_bytes(words: &[u32]) -> Vec<u8> {
+        words.iter().flat_map(|w| w.to_be_bytes()).collect()
+    }
+
+    // published XXTEA test vectors
+    const VECTORS: [([u32; 4], [u32; 2], [u32; 2]); 3] = [
+        ([0, 0, 0, 0], [0, 0], [0x053704ab, 0x575d8c80]),
+        ([0, 0, 0, 0], [0x01020304, 0x05060708], [0xe6911910, 0x0c35dcda]),
+        ([0x00112233, 0x44556677, 0x8899aabb, 0xccddeeff], [0x01020304, 0x05060708], [0x961d49fc, 0x61ff12d6]),
+    ];
+
+    #[test]
+    fn known_vectors() {
+        for (key, plain, cipher) in VECTORS {
+            let mut block = to_bytes(&plain);
+            encrypt(&key, &mut block);
+            assert_eq!(block, to_bytes(&cipher));
+            decrypt(&key, &mut block);
+            assert_eq!(block, to_bytes(&plain));
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn unaligned_round_trip() {
+        let plain: Vec<u8> = (0..103).collect();
+        let mut buf = [0u8; 105];
+        buf[1..104].copy_from_slice(&plain);
+
+        encrypt(&TEA_KEY, &mut buf[1..104]);
+        assert_ne!(&buf[1..101], &plain[..100]);
+        // the partial word at the end isn't touched
+        assert_eq!(&buf[101..104], &plain[100..]);
+
+        decrypt(&TEA_KEY, &mut buf[1..104]);
+        assert_eq!(&buf[1..104], &plain[..]);
+    }
+
+    // same output as the original implementation, which didn't special case single words
+    #[test]
+    fn single_word_is_encrypted() {
+        let mut block = to_bytes(&[0x01020304]);
+        encrypt(&TEA_KEY, &mut block);
+        assert_eq!(block, to_bytes(&[0x9ebc7e45]));
+    }
+}